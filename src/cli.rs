@@ -0,0 +1,272 @@
+//! Command-line interface module.
+//!
+//! Ready-made command parser for the servers built on the kit, so every server gets the same CLI:
+//!
+//! ```text
+//! server-example [--config <FILE>] [--profile <PROFILE>] [COMMAND]
+//!
+//! Commands:
+//!   run            Starts the server (default)
+//!   check-config   Checks the configuration and prints all the problems found
+//!   print-config   Prints the effective configuration with secrets redacted
+//!   print-openapi  Prints the OpenAPI document of the server
+//!   version        Prints the server's and the kit's versions
+//! ```
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use salvo::Router;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+use cc_utils::prelude::*;
+
+use crate::config::{ConfigFormat, ConfigSource};
+use crate::generic_setup::{GenericSetup, load_generic_state};
+use crate::startup::{get_root_router_autoinject, start};
+use crate::validation::parse_generic_config;
+
+/// Command-line arguments.
+#[derive(Debug, Parser)]
+pub struct Cli {
+  /// Configuration file to use instead of searching for it.
+  #[arg(short, long, global = true)]
+  pub config: Option<PathBuf>,
+  /// Configuration profile.
+  #[arg(short, long, global = true)]
+  pub profile: Option<String>,
+  #[command(subcommand)]
+  pub command: Option<CliCommand>,
+}
+
+/// Commands.
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+  /// Starts the server (default).
+  Run,
+  /// Checks the configuration and prints all the problems found.
+  CheckConfig,
+  /// Prints the effective configuration with secrets redacted.
+  PrintConfig {
+    /// Output format.
+    #[arg(short, long, value_enum, default_value = "yaml")]
+    format: ConfigFormat,
+  },
+  /// Prints the OpenAPI document of the server.
+  #[cfg(feature = "oapi")]
+  PrintOpenapi,
+  /// Prints the server's and the kit's versions.
+  Version,
+}
+
+impl Cli {
+  /// Returns the configuration source selected by the arguments.
+  pub fn config_source(&self, app_name: &str) -> ConfigSource {
+    let mut source = ConfigSource::with_profile(app_name, self.profile.as_deref());
+    if let Some(path) = &self.config {
+      source = source.path(path);
+    }
+    source
+  }
+}
+
+/// Parses the command-line arguments and runs the command.
+///
+/// `routes` builds your routes from the configuration; for `run` they are pushed into the root router, for
+/// `print-openapi` the document is generated from them without starting anything.
+///
+/// ```rust,ignore
+/// #[tokio::main]
+/// async fn main() {
+///   run_cli::<Setup, _>("server-example", env!("CARGO_PKG_VERSION"), |_setup| tests_router()).await.unwrap();
+/// }
+/// ```
+///
+/// `check-config`, `print-config` and `print-openapi` exit with code 1 if the configuration has problems.
+pub async fn run_cli<T, F>(app_name: &str, version: &'static str, routes: F) -> MResult<()>
+where
+  T: DeserializeOwned + Serialize + GenericSetup + Send + Sync + Clone + 'static,
+  F: FnOnce(&T) -> Router,
+{
+  let matches = Cli::command().name(app_name.to_string()).version(version).get_matches();
+  let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+  let source = cli.config_source(app_name);
+
+  match cli.command.unwrap_or(CliCommand::Run) {
+    CliCommand::Run => {
+      let setup = source.load::<T>().await?;
+      let state = load_generic_state(&setup).await?;
+      let router = get_root_router_autoinject(&state, setup.clone()).push(routes(&setup));
+      let (server, _handle) = start(state, &setup, router).await?;
+      server.await;
+    }
+    CliCommand::CheckConfig => {
+      let report = source.check::<T>();
+      println!("{}", report);
+      if !report.is_ok() {
+        std::process::exit(1);
+      }
+    }
+    CliCommand::PrintConfig { format } => {
+      let setup = parse_or_exit::<T>(&source);
+      println!("{}", print_config(&setup, format)?.trim_end());
+    }
+    #[cfg(feature = "oapi")]
+    CliCommand::PrintOpenapi => {
+      let setup = parse_or_exit::<T>(&source);
+      let data = setup.generic_values();
+      let doc = crate::startup::openapi_doc(
+        data.oapi_name.as_deref().unwrap_or(app_name),
+        data.oapi_ver.as_deref().unwrap_or(version),
+        &routes(&setup),
+      );
+      println!(
+        "{}",
+        doc
+          .to_pretty_json()
+          .map_err(|e| ErrorResponse::from(e.to_string()).with_500_pub().build())?
+      );
+    }
+    CliCommand::Version => {
+      println!("{} {}", app_name, version);
+      println!("cc-server-kit {}", env!("CARGO_PKG_VERSION"));
+    }
+  }
+
+  Ok(())
+}
+
+/// Loads the configuration without waiting for `server_port_achiever`; prints the problems and exits on failure.
+fn parse_or_exit<T: DeserializeOwned + GenericSetup>(source: &ConfigSource) -> T {
+  let (config, report) = parse_generic_config::<T>(source);
+  match config.filter(|_| report.is_ok()) {
+    Some(config) => config,
+    None => {
+      eprintln!("{}", report);
+      std::process::exit(1);
+    }
+  }
+}
+
+fn strip_nulls(value: &mut Value) {
+  match value {
+    Value::Mapping(mapping) => {
+      mapping.retain(|_, value| !value.is_null());
+      mapping.values_mut().for_each(strip_nulls);
+    }
+    Value::Sequence(sequence) => sequence.iter_mut().for_each(strip_nulls),
+    _ => {}
+  }
+}
+
+/// Serializes the effective configuration with the secrets redacted.
+///
+/// The kit's fields are printed with their defaults applied (see `GenericValues::with_defaults`); the other values
+/// which aren't set are omitted.
+pub fn print_config<T: Serialize + GenericSetup + Clone>(setup: &T, format: ConfigFormat) -> MResult<String> {
+  let to_error = |e: &dyn std::fmt::Display| ErrorResponse::from(e.to_string()).with_500_pub().build();
+
+  let mut setup = setup.clone();
+  let values = setup.generic_values().clone().with_defaults();
+  *setup.generic_values_mut() = values;
+  let secrets = &setup.generic_values().config_origin.secrets;
+  let mut document = serde_yaml::to_value(&setup).map_err(|e| to_error(&e))?;
+  secrets.redact_document(&mut document);
+  strip_nulls(&mut document);
+
+  let text = match format {
+    ConfigFormat::Yaml => serde_yaml::to_string(&document).map_err(|e| to_error(&e))?,
+    ConfigFormat::Toml => toml::to_string_pretty(&document).map_err(|e| to_error(&e))?,
+    ConfigFormat::Json => serde_json::to_string_pretty(&document).map_err(|e| to_error(&e))?,
+  };
+  Ok(secrets.redact(&text))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde::Deserialize;
+
+  use crate::generic_setup::GenericValues;
+
+  #[derive(Clone, Deserialize, Serialize)]
+  struct Setup {
+    #[serde(flatten)]
+    generic_values: GenericValues,
+    database_url: String,
+    pool_size: Option<u32>,
+  }
+
+  impl GenericSetup for Setup {
+    fn generic_values(&self) -> &GenericValues {
+      &self.generic_values
+    }
+    fn generic_values_mut(&mut self) -> &mut GenericValues {
+      &mut self.generic_values
+    }
+  }
+
+  #[test]
+  fn arguments_are_parsed() {
+    let cli = Cli::try_parse_from(["app"]).unwrap();
+    assert!(cli.config.is_none() && cli.profile.is_none() && cli.command.is_none());
+
+    let cli = Cli::try_parse_from(["app", "--config", "/srv/app.toml", "print-config", "-f", "json"]).unwrap();
+    assert_eq!(cli.config, Some(PathBuf::from("/srv/app.toml")));
+    assert!(matches!(
+      cli.command,
+      Some(CliCommand::PrintConfig {
+        format: ConfigFormat::Json
+      })
+    ));
+    assert_eq!(
+      cli.config_source("app").find_base_file(),
+      Some(PathBuf::from("/srv/app.toml"))
+    );
+
+    let cli = Cli::try_parse_from(["app", "check-config", "--profile", "prod"]).unwrap();
+    assert_eq!(cli.profile.as_deref(), Some("prod"));
+    assert!(matches!(cli.command, Some(CliCommand::CheckConfig)));
+
+    assert!(Cli::try_parse_from(["app", "print-config", "--format", "xml"]).is_err());
+    assert!(Cli::try_parse_from(["app", "serve"]).is_err());
+  }
+
+  #[test]
+  fn effective_configuration_is_printed_with_defaults_and_without_secrets() {
+    let dir = std::env::temp_dir().join(format!("cc-cli-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("password"), "p@ssw0rd\n").unwrap();
+    std::fs::write(
+      dir.join("app.yaml"),
+      format!(
+        "startup_type: http_localhost\ndatabase_url: postgres://app:${{file:{}}}@db/app\n",
+        dir.join("password").display()
+      ),
+    )
+    .unwrap();
+    std::fs::write(dir.join("app.prod.yaml"), "log_level: warn\n").unwrap();
+
+    let cli = Cli::try_parse_from(["app", "-c", dir.join("app.yaml").to_str().unwrap(), "-p", "prod"]).unwrap();
+    let (setup, report) = parse_generic_config::<Setup>(&cli.config_source("app"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(report.is_ok(), "{}", report);
+    let setup = setup.unwrap();
+    assert_eq!(setup.database_url, "postgres://app:p@ssw0rd@db/app");
+
+    let text = print_config(&setup, ConfigFormat::Yaml).unwrap();
+    let document = serde_yaml::from_str::<Value>(&text).unwrap();
+    assert_eq!(document["database_url"], Value::from("***"));
+    assert_eq!(document["log_level"], Value::from("warn"));
+    assert_eq!(document["health_check_timeout"], Value::from(5));
+    assert!(document.get("pool_size").is_none());
+    assert!(!text.contains("p@ssw0rd"));
+
+    let text = print_config(&setup, ConfigFormat::Json).unwrap();
+    let document = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+    assert_eq!(document["database_url"], "***");
+    let text = print_config(&setup, ConfigFormat::Toml).unwrap();
+    assert!(text.contains("database_url = \"***\""));
+  }
+}
@@ -0,0 +1,356 @@
+//! Client certificates module.
+//!
+//! The HTTPS listeners with `ssl_client_ca_path` terminate TLS with `ClientCertListener` instead of Salvo's rustls one,
+//! so the verified certificate of every connection is known; `ClientCertHoop` puts its subject and SANs into the
+//! `Depot` as `ClientCert`:
+//!
+//! ```rust,ignore
+//! #[handler]
+//! async fn whoami(depot: &mut Depot) -> String {
+//!   match depot.obtain::<ClientCert>() {
+//!     Ok(cert) => format!("{} ({:?})", cert.subject, cert.uris),
+//!     Err(_) => "anonymous".into(),
+//!   }
+//! }
+//! ```
+//!
+//! Salvo builds the requests from the connection without any extension point, so the connection registers its
+//! certificate in `ClientCerts` under its addresses for as long as it's open, and the hoop looks it up by the
+//! request's addresses. QUIC connections don't expose the certificate, so `ssl_client_ca_path` is rejected for the
+//! `quinn` and `quinn_only` listeners.
+
+use rustls::ServerConfig;
+use rustls::pki_types::CertificateDer;
+use salvo::conn::{Accepted, Acceptor, Holding, Listener, StraightStream};
+use salvo::fuse::FuseFactory;
+use salvo::http::uri::Scheme;
+use salvo::http::{HttpConnection, Version};
+use salvo::prelude::*;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Result as IoResult;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::watch;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::{Accept, TlsAcceptor};
+use x509_parser::extensions::GeneralName;
+
+/// Verified certificate the client has presented, injected into the `Depot` by `ClientCertHoop`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClientCert {
+  /// Subject's distinguished name, e.g. `O=Example, CN=billing`.
+  pub subject: String,
+  /// DNS names from the subject alternative names.
+  pub dns_names: Vec<String>,
+  /// URIs from the subject alternative names, e.g. SPIFFE IDs.
+  pub uris: Vec<String>,
+  /// Email addresses from the subject alternative names.
+  pub emails: Vec<String>,
+  /// IP addresses from the subject alternative names.
+  pub ip_addresses: Vec<IpAddr>,
+  /// The certificate itself, in DER.
+  pub der: Vec<u8>,
+}
+
+impl ClientCert {
+  /// Parses the subject and the subject alternative names of the certificate.
+  pub fn parse(der: &[u8]) -> Result<Self, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| e.to_string())?;
+    let mut client_cert = ClientCert {
+      subject: cert.subject().to_string(),
+      der: der.to_vec(),
+      ..Default::default()
+    };
+    let Some(names) = cert.subject_alternative_name().map_err(|e| e.to_string())? else {
+      return Ok(client_cert);
+    };
+    for name in &names.value.general_names {
+      match name {
+        GeneralName::DNSName(name) => client_cert.dns_names.push(name.to_string()),
+        GeneralName::URI(uri) => client_cert.uris.push(uri.to_string()),
+        GeneralName::RFC822Name(email) => client_cert.emails.push(email.to_string()),
+        GeneralName::IPAddress(bytes) => {
+          if let Ok(octets) = <[u8; 4]>::try_from(*bytes) {
+            client_cert.ip_addresses.push(IpAddr::from(octets));
+          } else if let Ok(octets) = <[u8; 16]>::try_from(*bytes) {
+            client_cert.ip_addresses.push(IpAddr::from(octets));
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(client_cert)
+  }
+}
+
+/// Local and remote addresses of the connection.
+type ConnAddrs = (SocketAddr, SocketAddr);
+
+/// Verified client certificates of the open connections by their local and remote addresses, shared by the listeners
+/// verifying the clients and `ClientCertHoop`.
+#[derive(Clone, Default)]
+pub(crate) struct ClientCerts(Arc<Mutex<HashMap<ConnAddrs, Arc<ClientCert>>>>);
+
+impl ClientCerts {
+  fn get(&self, addrs: &ConnAddrs) -> Option<Arc<ClientCert>> {
+    self.0.lock().ok()?.get(addrs).cloned()
+  }
+
+  /// Records the certificate until the returned guard is dropped with the connection.
+  fn register(&self, addrs: ConnAddrs, cert: ClientCert) -> Option<Registration> {
+    self.0.lock().ok()?.insert(addrs, Arc::new(cert));
+    Some(Registration {
+      certs: self.clone(),
+      addrs,
+    })
+  }
+}
+
+/// Removes the connection's certificate when the connection is closed.
+struct Registration {
+  certs: ClientCerts,
+  addrs: ConnAddrs,
+}
+
+impl Drop for Registration {
+  fn drop(&mut self) {
+    if let Ok(mut certs) = self.certs.0.lock() {
+      certs.remove(&self.addrs);
+    }
+  }
+}
+
+/// Hoop injecting the connection's `ClientCert` into the `Depot`; `start_with_service` installs it on the service.
+pub(crate) struct ClientCertHoop(pub(crate) ClientCerts);
+
+#[handler]
+impl ClientCertHoop {
+  async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    if let (Some(local), Some(remote)) = (
+      req.local_addr().clone().into_std(),
+      req.remote_addr().clone().into_std(),
+    ) && let Some(cert) = self.0.get(&(local, remote))
+    {
+      depot.inject(ClientCert::clone(&cert));
+    }
+    ctrl.call_next(req, depot, res).await;
+  }
+}
+
+/// Listener terminating TLS with the reloaded configurations and recording the clients' certificates.
+pub(crate) struct ClientCertListener<L> {
+  inner: L,
+  configs: watch::Receiver<Arc<ServerConfig>>,
+  certs: ClientCerts,
+}
+
+impl<L> ClientCertListener<L> {
+  pub(crate) fn new(inner: L, configs: watch::Receiver<Arc<ServerConfig>>, certs: ClientCerts) -> Self {
+    Self { inner, configs, certs }
+  }
+}
+
+impl<L> Listener for ClientCertListener<L>
+where
+  L: Listener + Send,
+  L::Acceptor: Send + 'static,
+  <L::Acceptor as Acceptor>::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  type Acceptor = ClientCertAcceptor<L::Acceptor>;
+
+  async fn try_bind(self) -> salvo::Result<Self::Acceptor> {
+    let inner = self.inner.try_bind().await?;
+    let holdings = inner
+      .holdings()
+      .iter()
+      .map(|holding| {
+        let mut holding = holding.clone();
+        for version in [Version::HTTP_11, Version::HTTP_2] {
+          if !holding.http_versions.contains(&version) {
+            holding.http_versions.push(version);
+          }
+        }
+        holding.http_scheme = Scheme::HTTPS;
+        holding
+      })
+      .collect();
+    Ok(ClientCertAcceptor {
+      inner,
+      configs: self.configs,
+      certs: self.certs,
+      holdings,
+    })
+  }
+}
+
+pub(crate) struct ClientCertAcceptor<A> {
+  inner: A,
+  configs: watch::Receiver<Arc<ServerConfig>>,
+  certs: ClientCerts,
+  holdings: Vec<Holding>,
+}
+
+impl<A> Acceptor for ClientCertAcceptor<A>
+where
+  A: Acceptor + Send + 'static,
+  A::Conn: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+  type Conn = StraightStream<ClientCertStream<A::Conn>>;
+
+  fn holdings(&self) -> &[Holding] {
+    &self.holdings
+  }
+
+  async fn accept(
+    &mut self,
+    fuse_factory: Option<Arc<dyn FuseFactory + Sync + Send + 'static>>,
+  ) -> IoResult<Accepted<Self::Conn>> {
+    let accepted = self.inner.accept(fuse_factory).await?;
+    let acceptor = TlsAcceptor::from(self.configs.borrow().clone());
+    let addrs = accepted
+      .local_addr
+      .clone()
+      .into_std()
+      .zip(accepted.remote_addr.clone().into_std());
+    let certs = self.certs.clone();
+    let mut accepted = accepted.map_conn(|conn| {
+      let fusewire = conn.fusewire();
+      let stream = ClientCertStream {
+        state: State::Handshaking(acceptor.accept(conn)),
+        addrs,
+        certs,
+        _registration: None,
+      };
+      StraightStream::new(stream, fusewire)
+    });
+    accepted.http_scheme = Scheme::HTTPS;
+    Ok(accepted)
+  }
+}
+
+enum State<C> {
+  Handshaking(Accept<C>),
+  Streaming(TlsStream<C>),
+}
+
+/// TLS connection; the handshake is done by the connection's task, so slow clients don't hold the accepting loop. The
+/// client's certificate is registered once the handshake is done, before any request is read.
+pub(crate) struct ClientCertStream<C> {
+  state: State<C>,
+  addrs: Option<ConnAddrs>,
+  certs: ClientCerts,
+  _registration: Option<Registration>,
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> ClientCertStream<C> {
+  fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<IoResult<&mut TlsStream<C>>> {
+    if let State::Handshaking(accept) = &mut self.state {
+      let stream = ready!(Pin::new(accept).poll(cx))?;
+      self._registration = self.register(&stream);
+      self.state = State::Streaming(stream);
+    }
+    match &mut self.state {
+      State::Streaming(stream) => Poll::Ready(Ok(stream)),
+      State::Handshaking(_) => Poll::Pending,
+    }
+  }
+
+  fn register(&self, stream: &TlsStream<C>) -> Option<Registration> {
+    let addrs = self.addrs?;
+    let der: &CertificateDer = stream.get_ref().1.peer_certificates()?.first()?;
+    let cert = ClientCert::parse(der)
+      .inspect_err(|e| tracing::warn!("Failed to parse the client certificate: {}", e))
+      .ok()?;
+    self.certs.register(addrs, cert)
+  }
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> AsyncRead for ClientCertStream<C> {
+  fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<IoResult<()>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_read(cx, buf)
+  }
+}
+
+impl<C: AsyncRead + AsyncWrite + Unpin> AsyncWrite for ClientCertStream<C> {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_write(cx, buf)
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+    let stream = ready!(self.get_mut().poll_handshake(cx))?;
+    Pin::new(stream).poll_flush(cx)
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+    match &mut self.get_mut().state {
+      State::Handshaking(_) => Poll::Ready(Ok(())),
+      State::Streaming(stream) => Pin::new(stream).poll_shutdown(cx),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rustls::pki_types::pem::PemObject;
+
+  const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIICFTCCAbugAwIBAgIUbiizm9wumB49fpqhP7Tym/ZtMUkwCgYIKoZIzj0EAwIw
+JDEQMA4GA1UECgwHRXhhbXBsZTEQMA4GA1UEAwwHYmlsbGluZzAgFw0yNjEwMTYx
+OTE5NTlaGA8yMTI2MDkyMjE5MTk1OVowJDEQMA4GA1UECgwHRXhhbXBsZTEQMA4G
+A1UEAwwHYmlsbGluZzBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABJW229cMtdtI
+QKmttV0OVHN5PQEH8Gu95dkrzF48U5FR4v1sS5flvRNyoDYjyPoD2G+Z+1YsPweh
+MpVLHaiMODajgcgwgcUwHQYDVR0OBBYEFBP3BNLpPo8Ko1xgZQnH5lTZzRnkMB8G
+A1UdIwQYMBaAFBP3BNLpPo8Ko1xgZQnH5lTZzRnkMA8GA1UdEwEB/wQFMAMBAf8w
+cgYDVR0RBGswaYIQYmlsbGluZy5pbnRlcm5hbIYsc3BpZmZlOi8vY2x1c3Rlci5s
+b2NhbC9ucy9kZWZhdWx0L3NhL2JpbGxpbmeBD29wc0BleGFtcGxlLmNvbYcECgAA
+B4cQAAAAAAAAAAAAAAAAAAAAATAKBggqhkjOPQQDAgNIADBFAiEArTSfvjI5zdI3
+p4tBfc+Sb7dkDqvTjpKd1D+SQ2onKM8CIHehHuB9doRm5/ZKKa90Zcwhm77htReg
+2QiLnb27v5lD
+-----END CERTIFICATE-----
+";
+
+  #[test]
+  fn parses_subject_and_alternative_names() {
+    let der = CertificateDer::from_pem_slice(CERT.as_bytes()).unwrap();
+    let cert = ClientCert::parse(&der).unwrap();
+    assert!(cert.subject.contains("O=Example"));
+    assert!(cert.subject.contains("CN=billing"));
+    assert_eq!(cert.dns_names, vec!["billing.internal"]);
+    assert_eq!(cert.uris, vec!["spiffe://cluster.local/ns/default/sa/billing"]);
+    assert_eq!(cert.emails, vec!["ops@example.com"]);
+    assert_eq!(
+      cert.ip_addresses,
+      vec!["10.0.0.7".parse::<IpAddr>().unwrap(), "::1".parse::<IpAddr>().unwrap()]
+    );
+    assert_eq!(cert.der, der.as_ref());
+  }
+
+  #[test]
+  fn certificates_are_kept_while_connections_are_open() {
+    let certs = ClientCerts::default();
+    let addrs = ("127.0.0.1:8800".parse().unwrap(), "127.0.0.1:50000".parse().unwrap());
+    let other = ("127.0.0.1:8800".parse().unwrap(), "127.0.0.1:50001".parse().unwrap());
+    let cert = ClientCert {
+      subject: "CN=billing".into(),
+      ..Default::default()
+    };
+
+    let registration = certs.register(addrs, cert.clone()).unwrap();
+    assert_eq!(certs.get(&addrs).as_deref(), Some(&cert));
+    assert!(certs.get(&other).is_none());
+    drop(registration);
+    assert!(certs.get(&addrs).is_none());
+  }
+
+  #[test]
+  fn rejects_garbage() {
+    assert!(ClientCert::parse(b"not a certificate").is_err());
+  }
+}
@@ -0,0 +1,858 @@
+//! Configuration layering module.
+//!
+//! The configuration files are first parsed into plain documents and deep-merged in the following order (later ones
+//! take precedence):
+//!
+//! 1. fragments listed in `include:` key of the base file;
+//! 2. base file `{app_name}.yaml` (see `ConfigSource` for where it is searched);
+//! 3. profile file `{app_name}.{profile}.yaml` (with its own includes);
+//! 4. environment variables `CC_{APP_NAME}_{KEY}`.
+//!
+//! Then the secret references (`${env:NAME}`, `${file:/path}`) are resolved; see `secrets` module.
+//!
+//! Every file can be written in YAML, TOML or JSON; the format is chosen by the file extension (see `ConfigFormat`).
+//!
+//! Only after that the document is deserialized into the user's `GenericSetup` type. This way the profiles and
+//! overrides work for custom fields too.
+
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use cc_utils::prelude::*;
+
+use crate::generic_setup::{GenericSetup, achieve_server_port};
+use crate::secrets::{Secrets, resolve_secrets};
+use crate::validation::{ConfigIssue, ConfigReport, parse_generic_config};
+
+/// Key which lists the configuration fragments to include.
+pub const INCLUDE_KEY: &str = "include";
+
+/// Environment variables with these names (after the prefix) configure the loader itself and are not applied
+/// as overrides.
+const RESERVED_ENV_KEYS: &[&str] = &["PROFILE", CONFIG_DIRS_ENV_KEY];
+
+/// Environment variable (after the prefix) which lists additional directories to search the configuration in.
+const CONFIG_DIRS_ENV_KEY: &str = "CONFIG_DIRS";
+
+/// Configuration file format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ConfigFormat {
+  Yaml,
+  Toml,
+  Json,
+}
+
+/// Configuration file extensions in the search order.
+pub const CONFIG_EXTENSIONS: &[&str] = &["yaml", "yml", "toml", "json"];
+
+impl ConfigFormat {
+  /// Detects the format by the file extension; unknown extensions are treated as YAML.
+  pub fn from_path(path: &Path) -> Self {
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("toml") => Self::Toml,
+      Some("json") => Self::Json,
+      _ => Self::Yaml,
+    }
+  }
+
+  /// Parses the file contents into the configuration document.
+  fn parse(self, buffer: &str, path: &Path) -> Result<Value, ConfigIssue> {
+    match self {
+      Self::Yaml => serde_yaml::from_str(buffer).map_err(|e| {
+        let issue = ConfigIssue::new(e.to_string()).in_file(path);
+        match e.location() {
+          Some(location) => issue.with_location(location.line(), location.column()),
+          None => issue,
+        }
+      }),
+      Self::Toml => {
+        let document = toml::from_str::<toml::Value>(buffer).map_err(|e| {
+          let issue = ConfigIssue::new(e.message().trim().replace('\n', "; ")).in_file(path);
+          match e.span() {
+            Some(span) => {
+              let (line, column) = line_column(buffer, span.start);
+              issue.with_location(line, column)
+            }
+            None => issue,
+          }
+        })?;
+        serde_yaml::to_value(document).map_err(|e| ConfigIssue::new(e.to_string()).in_file(path))
+      }
+      Self::Json => {
+        let document = serde_json::from_str::<serde_json::Value>(buffer).map_err(|e| {
+          let message = e.to_string();
+          let location = format!(" at line {} column {}", e.line(), e.column());
+          ConfigIssue::new(message.strip_suffix(&location).unwrap_or(&message))
+            .in_file(path)
+            .with_location(e.line(), e.column())
+        })?;
+        serde_yaml::to_value(document).map_err(|e| ConfigIssue::new(e.to_string()).in_file(path))
+      }
+    }
+  }
+}
+
+/// Converts the byte offset to the line and column, both starting from 1.
+fn line_column(buffer: &str, offset: usize) -> (usize, usize) {
+  let before = &buffer[..offset.min(buffer.len())];
+  let line = before.matches('\n').count() + 1;
+  let column = before.rsplit('\n').next().map_or(0, |last| last.chars().count()) + 1;
+  (line, column)
+}
+
+/// Deserializes the file alone into `T` and returns where it fails, if it fails at the same path with the same message
+/// as the merged configuration does. Serde reports no location for the values it has buffered, e.g. for the fields of
+/// the flattened structs.
+fn error_location<T: DeserializeOwned>(
+  buffer: &str,
+  format: ConfigFormat,
+  path: &str,
+  message: &str,
+) -> Option<(usize, usize)> {
+  // The parsers word the type errors differently from `serde_yaml::Value`, so only the missing fields, which are
+  // reported at the mapping missing them, are compared by the message.
+  let same_error = |error: &serde_path_to_error::Path, error_message: String| {
+    error.to_string().trim_start_matches('.') == path
+      && (!message.starts_with("missing field") || error_message.contains(message))
+  };
+  match format {
+    ConfigFormat::Yaml => {
+      let e = serde_path_to_error::deserialize::<_, T>(serde_yaml::Deserializer::from_str(buffer)).err()?;
+      let location = e.inner().location()?;
+      same_error(e.path(), e.inner().to_string()).then(|| (location.line(), location.column()))
+    }
+    ConfigFormat::Toml => {
+      let e = serde_path_to_error::deserialize::<_, T>(toml::Deserializer::new(buffer)).err()?;
+      let span = e.inner().span()?;
+      same_error(e.path(), e.inner().message().to_owned()).then(|| line_column(buffer, span.start))
+    }
+    ConfigFormat::Json => {
+      let e = serde_path_to_error::deserialize::<_, T>(&mut serde_json::Deserializer::from_str(buffer)).err()?;
+      (e.inner().line() > 0 && same_error(e.path(), e.inner().to_string()))
+        .then(|| (e.inner().line(), e.inner().column()))
+    }
+  }
+}
+
+/// Finds where the configuration files cause the deserialization error at the path: the latest file which fails to
+/// deserialize alone with the same error. Returns the file, the line and the column.
+pub(crate) fn locate_error<T: DeserializeOwned>(
+  files: &[PathBuf],
+  path: &str,
+  message: &str,
+) -> Option<(PathBuf, usize, usize)> {
+  files.iter().rev().find_map(|file| {
+    let buffer = std::fs::read_to_string(file).ok()?;
+    let (line, column) = error_location::<T>(&buffer, ConfigFormat::from_path(file), path, message)?;
+    Some((file.clone(), line, column))
+  })
+}
+
+/// Describes where the effective configuration came from.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOrigin {
+  /// Source the configuration was loaded from; hot reload reads the configuration from it again.
+  pub source: ConfigSource,
+  /// Base configuration file which was found by the search.
+  pub base_file: Option<PathBuf>,
+  /// Configuration files which were read, in the order they were merged.
+  pub files: Vec<PathBuf>,
+  /// Selected configuration profile.
+  pub profile: Option<String>,
+  /// Environment variables which have overridden the file values, in the order they were applied.
+  pub env_overrides: Vec<String>,
+  /// Secrets resolved from `${env:...}` and `${file:...}` references.
+  pub secrets: Secrets,
+}
+
+/// Returns the prefix of environment variables for the application, e.g. `CC_MY_APP_` for `my-app`.
+pub fn env_prefix(app_name: &str) -> String {
+  let app_name = app_name
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() {
+        c.to_ascii_uppercase()
+      } else {
+        '_'
+      }
+    })
+    .collect::<String>();
+  format!("CC_{}_", app_name)
+}
+
+/// Converts environment variable's name (without prefix) to the path in the configuration document.
+///
+/// Nested keys are separated with double underscore: `DATABASE__URL` becomes `database.url`.
+fn env_key_to_path(key: &str) -> Vec<String> {
+  key
+    .split("__")
+    .filter(|part| !part.is_empty())
+    .map(|part| part.to_lowercase())
+    .collect()
+}
+
+/// Parses environment variable's value as YAML scalar, so `8801` is a number and `true` is a boolean.
+///
+/// If you need a string which looks like a number, quote it: `CC_APP_OAPI_VER='"1.0"'`.
+fn env_value(raw: String) -> Value {
+  match serde_yaml::from_str::<Value>(&raw) {
+    Ok(value) => value,
+    Err(_) => Value::String(raw),
+  }
+}
+
+fn set_path(document: &mut Value, path: &[String], value: Value) {
+  let Some((key, rest)) = path.split_first() else {
+    *document = value;
+    return;
+  };
+
+  if !document.is_mapping() {
+    *document = Value::Mapping(Mapping::new());
+  }
+  let mapping = document.as_mapping_mut().unwrap();
+  let key = Value::String(key.clone());
+  if !mapping.contains_key(&key) {
+    mapping.insert(key.clone(), Value::Null);
+  }
+  set_path(mapping.get_mut(&key).unwrap(), rest, value);
+}
+
+/// Applies the given variables to the configuration document.
+///
+/// Returns the names of variables which have been applied, sorted by name. The variables with names or values which
+/// aren't valid Unicode are skipped.
+pub(crate) fn apply_env_overrides(
+  document: &mut Value,
+  prefix: &str,
+  vars: impl IntoIterator<Item = (OsString, OsString)>,
+) -> Vec<String> {
+  let mut vars = vars
+    .into_iter()
+    .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+    .filter(|(name, _)| name.starts_with(prefix) && name.len() > prefix.len())
+    .filter(|(name, _)| !RESERVED_ENV_KEYS.contains(&&name[prefix.len()..]))
+    .collect::<Vec<_>>();
+  vars.sort_by(|a, b| a.0.cmp(&b.0));
+
+  let mut applied = vec![];
+  for (name, raw) in vars {
+    let path = env_key_to_path(&name[prefix.len()..]);
+    if path.is_empty() {
+      continue;
+    }
+    set_path(document, &path, env_value(raw));
+    applied.push(name);
+  }
+  applied
+}
+
+/// Deep-merges `overlay` into `base`: mappings are merged key by key, any other values are replaced.
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+  match (base, overlay) {
+    (Value::Mapping(base), Value::Mapping(overlay)) => {
+      for (key, value) in overlay {
+        match base.get_mut(&key) {
+          Some(existing) => merge(existing, value),
+          None => {
+            base.insert(key, value);
+          }
+        }
+      }
+    }
+    (_, Value::Null) => {}
+    (base, overlay) => *base = overlay,
+  }
+}
+
+fn include_paths(document: &mut Value, file: &Path, report: &mut ConfigReport) -> Vec<String> {
+  let Some(mapping) = document.as_mapping_mut() else {
+    return vec![];
+  };
+  let issue = || {
+    ConfigIssue::new("The `include` key must contain a path or a list of paths.")
+      .in_file(file)
+      .at(INCLUDE_KEY)
+  };
+  match mapping.remove(INCLUDE_KEY) {
+    None | Some(Value::Null) => vec![],
+    Some(Value::String(path)) => vec![path],
+    Some(Value::Sequence(paths)) => paths
+      .into_iter()
+      .filter_map(|path| match path {
+        Value::String(path) => Some(path),
+        _ => {
+          report.push(issue());
+          None
+        }
+      })
+      .collect(),
+    Some(_) => {
+      report.push(issue());
+      vec![]
+    }
+  }
+}
+
+/// Reads the configuration file and merges all its includes under it.
+///
+/// Include paths are relative to the including file. Problems are added to the report, and the unreadable files are
+/// treated as empty ones, so the rest of the configuration can still be checked.
+fn read_document(path: &Path, files: &mut Vec<PathBuf>, stack: &mut Vec<PathBuf>, report: &mut ConfigReport) -> Value {
+  let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+  if stack.contains(&canonical) {
+    report.push(ConfigIssue::new("The configuration file includes itself.").in_file(path));
+    return Value::Null;
+  }
+
+  let buffer = match std::fs::read_to_string(path) {
+    Ok(buffer) => buffer,
+    Err(e) => {
+      report.push(
+        ConfigIssue::new(format!(
+          "Failed to read the contents of the server configuration file: {}",
+          e
+        ))
+        .in_file(path),
+      );
+      return Value::Null;
+    }
+  };
+  let mut document = match ConfigFormat::from_path(path).parse(&buffer, path) {
+    Ok(document) => document,
+    Err(issue) => {
+      report.push(issue);
+      return Value::Null;
+    }
+  };
+
+  let includes = include_paths(&mut document, path, report);
+  if includes.is_empty() {
+    files.push(path.to_path_buf());
+    return document;
+  }
+
+  stack.push(canonical);
+  let base_dir = path.parent().unwrap_or(Path::new("."));
+  let mut merged = Value::Null;
+  for include in includes {
+    let fragment = read_document(&base_dir.join(include), files, stack, report);
+    merge(&mut merged, fragment);
+  }
+  stack.pop();
+
+  files.push(path.to_path_buf());
+  merge(&mut merged, document);
+  merged
+}
+
+/// Returns the first existing file `{dir}/{name}.{ext}`, trying extensions in `CONFIG_EXTENSIONS` order.
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+  CONFIG_EXTENSIONS
+    .iter()
+    .map(|ext| dir.join(format!("{}.{}", name, ext)))
+    .find(|path| path.is_file())
+}
+
+/// Where to look for the configuration.
+///
+/// By default the base file `{app_name}.{ext}` is searched in these directories, in order:
+///
+/// 1. directories listed in `CC_{APP_NAME}_CONFIG_DIRS` environment variable (separated like `PATH`);
+/// 2. the working directory;
+/// 3. `$XDG_CONFIG_HOME/{app_name}/` (or `~/.config/{app_name}/` if `XDG_CONFIG_HOME` isn't set);
+/// 4. `/etc/`.
+///
+/// ```rust,ignore
+/// let setup = ConfigSource::new("server-example")
+///   .path("/srv/server-example/config.toml")
+///   .profile("prod")
+///   .load::<Setup>()
+///   .await?;
+///
+/// // In tests:
+/// let report = ConfigSource::new("server-example").search_dirs([tmp_dir.path()]).check::<Setup>();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSource {
+  app_name: String,
+  profile: Option<String>,
+  path: Option<PathBuf>,
+  search_dirs: Vec<PathBuf>,
+}
+
+impl ConfigSource {
+  /// Creates the source with the default search directories.
+  pub fn new(app_name: impl Into<String>) -> Self {
+    let app_name = app_name.into();
+    let search_dirs = default_search_dirs(&app_name, |key| std::env::var_os(key));
+    Self {
+      app_name,
+      profile: None,
+      path: None,
+      search_dirs,
+    }
+  }
+
+  /// Creates the source with the default search directories and the optional profile.
+  pub fn with_profile(app_name: impl Into<String>, profile: Option<&str>) -> Self {
+    let source = Self::new(app_name);
+    match profile {
+      Some(profile) => source.profile(profile),
+      None => source,
+    }
+  }
+
+  /// Selects the profile; otherwise it is taken from `CC_{APP_NAME}_PROFILE` environment variable.
+  pub fn profile(mut self, profile: impl Into<String>) -> Self {
+    self.profile = Some(profile.into());
+    self
+  }
+
+  /// Uses the given file as the base configuration file instead of searching for it, e.g. from `--config` argument.
+  ///
+  /// The profile's file is searched next to it: `/srv/config.toml` with `prod` profile is merged with
+  /// `/srv/config.prod.toml` (or any other supported extension).
+  pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+    self.path = Some(path.into());
+    self
+  }
+
+  /// Replaces the search directories.
+  pub fn search_dirs<P: Into<PathBuf>>(mut self, dirs: impl IntoIterator<Item = P>) -> Self {
+    self.search_dirs = dirs.into_iter().map(Into::into).collect();
+    self
+  }
+
+  /// Adds the directory to the end of the search list.
+  pub fn add_search_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+    self.search_dirs.push(dir.into());
+    self
+  }
+
+  /// Returns the application name.
+  pub fn app_name(&self) -> &str {
+    &self.app_name
+  }
+
+  /// Returns the directories where the base configuration file is searched, in order.
+  pub fn dirs(&self) -> &[PathBuf] {
+    &self.search_dirs
+  }
+
+  /// Returns the base configuration file: the explicit path, or the first file found in the search directories.
+  ///
+  /// In each directory the extensions are tried in `CONFIG_EXTENSIONS` order.
+  pub fn find_base_file(&self) -> Option<PathBuf> {
+    match &self.path {
+      Some(path) => Some(path.clone()),
+      None => self.search_dirs.iter().find_map(|dir| find_file(dir, &self.app_name)),
+    }
+  }
+
+  /// Loads, checks and deserializes the configuration; see `load_generic_config_with_profile`.
+  pub async fn load<T: DeserializeOwned + GenericSetup>(&self) -> MResult<T> {
+    let (config, report) = parse_generic_config::<T>(self);
+    let Some(mut config) = config.filter(|_| report.is_ok()) else {
+      return Err(report.into());
+    };
+    achieve_server_port(config.generic_values_mut()).await?;
+    Ok(config)
+  }
+
+  /// Checks the configuration without starting anything; see `check_generic_config`.
+  pub fn check<T: DeserializeOwned + GenericSetup>(&self) -> ConfigReport {
+    parse_generic_config::<T>(self).1
+  }
+}
+
+/// Returns the default search directories of the application; `var` looks up the environment variables.
+fn default_search_dirs(app_name: &str, var: impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+  let mut dirs = vec![];
+  if let Some(env_dirs) = var(&format!("{}{}", env_prefix(app_name), CONFIG_DIRS_ENV_KEY)) {
+    dirs.extend(std::env::split_paths(&env_dirs).filter(|dir| !dir.as_os_str().is_empty()));
+  }
+  dirs.push(PathBuf::new());
+  if let Some(config_home) = var("XDG_CONFIG_HOME")
+    .filter(|dir| !dir.is_empty())
+    .map(PathBuf::from)
+    .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+  {
+    dirs.push(config_home.join(app_name));
+  }
+  dirs.push(PathBuf::from("/etc"));
+  dirs
+}
+
+/// Loads the merged configuration document for the application.
+///
+/// If the source has no profile, it is taken from the `CC_{APP_NAME}_PROFILE` environment variable.
+pub(crate) fn load_document(source: &ConfigSource, report: &mut ConfigReport) -> (Value, ConfigOrigin) {
+  let prefix = env_prefix(&source.app_name);
+  let profile = source
+    .profile
+    .clone()
+    .or_else(|| std::env::var(format!("{}PROFILE", prefix)).ok())
+    .filter(|profile| !profile.is_empty());
+
+  let mut files = vec![];
+  let mut document = Value::Null;
+
+  let base_file = source.find_base_file();
+  match &base_file {
+    Some(base_file) if !base_file.is_file() => {
+      report.push(ConfigIssue::new("The configuration file could not be found.").in_file(base_file))
+    }
+    Some(base_file) => {
+      document = read_document(base_file, &mut files, &mut vec![], report);
+
+      if let Some(profile) = &profile {
+        let dir = base_file.parent().unwrap_or(Path::new(""));
+        let stem = base_file.file_stem().and_then(|stem| stem.to_str()).unwrap_or(&source.app_name);
+        let name = format!("{}.{}", stem, profile);
+        match find_file(dir, &name) {
+          Some(profile_file) => {
+            let overlay = read_document(&profile_file, &mut files, &mut vec![], report);
+            merge(&mut document, overlay);
+          }
+          None => report.push(
+            ConfigIssue::new(format!(
+              "The configuration file for profile `{}` could not be found.",
+              profile
+            ))
+            .in_file(&dir.join(format!("{}.yaml", name))),
+          ),
+        }
+      }
+    }
+    None => report.push(ConfigIssue::new(format!(
+      "The server configuration could not be found. Create `{}.yaml` (or `.yml`, `.toml`, `.json`) in one of the directories: {}.",
+      source.app_name,
+      source
+        .search_dirs
+        .iter()
+        .map(|dir| match dir.as_os_str().is_empty() {
+          true => "`.`".to_string(),
+          false => format!("`{}`", dir.display()),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+    ))),
+  }
+
+  let env_overrides = apply_env_overrides(&mut document, &prefix, std::env::vars_os());
+  let secrets = resolve_secrets(&mut document, report);
+
+  (
+    document,
+    ConfigOrigin {
+      source: source.clone(),
+      base_file,
+      files,
+      profile,
+      env_overrides,
+      secrets,
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vars(vars: &[(&str, &str)]) -> Vec<(OsString, OsString)> {
+    vars
+      .iter()
+      .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+      .collect()
+  }
+
+  #[test]
+  fn env_key_is_split_by_double_underscore() {
+    assert_eq!(env_key_to_path("SERVER_PORT"), ["server_port"]);
+    assert_eq!(env_key_to_path("DATABASE__URL"), ["database", "url"]);
+    assert_eq!(env_key_to_path("__A____B__"), ["a", "b"]);
+    assert!(env_key_to_path("").is_empty());
+  }
+
+  #[test]
+  fn env_value_is_parsed_as_scalar() {
+    assert_eq!(env_value("8801".into()), Value::from(8801));
+    assert_eq!(env_value("true".into()), Value::from(true));
+    assert_eq!(env_value(r#""1.0""#.into()), Value::from("1.0"));
+    assert_eq!(env_value("{ not yaml".into()), Value::from("{ not yaml"));
+  }
+
+  #[test]
+  fn env_overrides_are_applied_by_prefix() {
+    let mut document: Value = serde_yaml::from_str("server_port: 8800\ndatabase:\n  url: a\n  pool: 4\n").unwrap();
+    let applied = apply_env_overrides(
+      &mut document,
+      "CC_APP_",
+      vars(&[
+        ("CC_APP_SERVER_PORT", "8801"),
+        ("CC_APP_DATABASE__URL", "b"),
+        ("CC_APP_", "ignored"),
+        ("CC_OTHER_SERVER_PORT", "1"),
+        ("PATH", "/bin"),
+      ]),
+    );
+    assert_eq!(applied, ["CC_APP_DATABASE__URL", "CC_APP_SERVER_PORT"]);
+    assert_eq!(
+      document,
+      serde_yaml::from_str::<Value>("server_port: 8801\ndatabase:\n  url: b\n  pool: 4\n").unwrap()
+    );
+  }
+
+  #[test]
+  fn env_overrides_create_missing_mappings() {
+    let mut document = Value::Null;
+    apply_env_overrides(&mut document, "CC_APP_", vars(&[("CC_APP_A__B__C", "1")]));
+    assert_eq!(document, serde_yaml::from_str::<Value>("a: { b: { c: 1 } }").unwrap());
+  }
+
+  #[test]
+  fn env_overrides_skip_reserved_keys() {
+    let mut document = Value::Null;
+    let applied = apply_env_overrides(&mut document, "CC_APP_", vars(&[("CC_APP_PROFILE", "prod")]));
+    assert!(applied.is_empty());
+    assert_eq!(document, Value::Null);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn env_overrides_skip_non_unicode_vars() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut document = Value::Null;
+    let mut vars = vars(&[("CC_APP_A", "1")]);
+    vars.push((OsString::from_vec(b"CC_APP_\xff".to_vec()), OsString::from("2")));
+    vars.push((OsString::from("CC_APP_B"), OsString::from_vec(b"\xff".to_vec())));
+    let applied = apply_env_overrides(&mut document, "CC_APP_", vars);
+    assert_eq!(applied, ["CC_APP_A"]);
+    assert_eq!(document, serde_yaml::from_str::<Value>("a: 1").unwrap());
+  }
+
+  fn yaml(text: &str) -> Value {
+    serde_yaml::from_str(text).unwrap()
+  }
+
+  #[test]
+  fn mappings_are_merged_key_by_key() {
+    let mut base = yaml("a: 1\nb: { c: 2, d: 3 }");
+    merge(&mut base, yaml("b: { d: 4, e: 5 }\nf: 6"));
+    assert_eq!(base, yaml("a: 1\nb: { c: 2, d: 4, e: 5 }\nf: 6"));
+  }
+
+  #[test]
+  fn sequences_are_replaced() {
+    let mut base = yaml("list: [1, 2, 3]");
+    merge(&mut base, yaml("list: [4]"));
+    assert_eq!(base, yaml("list: [4]"));
+  }
+
+  #[test]
+  fn null_overlay_keeps_base_value() {
+    let mut base = yaml("a: 1\nb: { c: 2 }");
+    merge(&mut base, yaml("a: null\nb: ~"));
+    assert_eq!(base, yaml("a: 1\nb: { c: 2 }"));
+
+    let mut base = yaml("a: 1");
+    merge(&mut base, Value::Null);
+    assert_eq!(base, yaml("a: 1"));
+  }
+
+  #[test]
+  fn null_base_value_is_replaced() {
+    let mut base = yaml("a: null");
+    merge(&mut base, yaml("a: { b: 1 }"));
+    assert_eq!(base, yaml("a: { b: 1 }"));
+  }
+
+  #[test]
+  fn scalar_and_mapping_replace_each_other() {
+    let mut base = yaml("a: { b: 1 }");
+    merge(&mut base, yaml("a: 2"));
+    assert_eq!(base, yaml("a: 2"));
+
+    let mut base = yaml("a: 2");
+    merge(&mut base, yaml("a: { b: 1 }"));
+    assert_eq!(base, yaml("a: { b: 1 }"));
+  }
+
+  #[test]
+  fn offset_is_converted_to_line_and_column() {
+    let buffer = "a: 1\nbé: 2\n";
+    assert_eq!(line_column(buffer, 0), (1, 1));
+    assert_eq!(line_column(buffer, 3), (1, 4));
+    assert_eq!(line_column(buffer, 5), (2, 1));
+    assert_eq!(line_column(buffer, 8), (2, 3));
+    assert_eq!(line_column(buffer, buffer.len()), (3, 1));
+    assert_eq!(line_column(buffer, 100), (3, 1));
+  }
+
+  #[derive(Debug, serde::Deserialize)]
+  #[allow(dead_code)]
+  struct Database {
+    url: String,
+    pool: u32,
+  }
+
+  #[derive(Debug, serde::Deserialize)]
+  #[allow(dead_code)]
+  struct Located {
+    server_port: u16,
+    database: Database,
+  }
+
+  /// Merges the files like the loader does and returns the path and the message of the deserialization error.
+  fn merged_error(buffers: &[(&str, ConfigFormat)]) -> (String, String) {
+    let mut document = Value::Null;
+    for (buffer, format) in buffers {
+      merge(&mut document, format.parse(buffer, Path::new("test")).unwrap());
+    }
+    let e = serde_path_to_error::deserialize::<_, Located>(document).unwrap_err();
+    (e.path().to_string(), e.inner().to_string())
+  }
+
+  #[test]
+  fn errors_are_located_by_the_parsers() {
+    let yaml = "server_port: 8800\ndatabase:\n  url: postgres://db\n  pool: many\n";
+    let (path, message) = merged_error(&[(yaml, ConfigFormat::Yaml)]);
+    assert_eq!(path, "database.pool");
+    assert_eq!(
+      error_location::<Located>(yaml, ConfigFormat::Yaml, &path, &message),
+      Some((4, 9))
+    );
+
+    let toml = "server_port = 8800\n\n[database]\nurl = \"postgres://db\"\npool = \"many\"\n";
+    let (path, message) = merged_error(&[(toml, ConfigFormat::Toml)]);
+    assert_eq!(
+      error_location::<Located>(toml, ConfigFormat::Toml, &path, &message),
+      Some((5, 8))
+    );
+
+    let json = "{\n  \"server_port\": 8800,\n  \"database\": { \"url\": \"postgres://db\", \"pool\": \"many\" }\n}\n";
+    let (path, message) = merged_error(&[(json, ConfigFormat::Json)]);
+    assert_eq!(
+      error_location::<Located>(json, ConfigFormat::Json, &path, &message),
+      Some((3, 54))
+    );
+  }
+
+  #[test]
+  fn error_is_located_in_the_file_causing_it() {
+    let dir = std::env::temp_dir().join(format!("cc-locate-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let base = dir.join("app.yaml");
+    let profile = dir.join("app.prod.yaml");
+    let base_buffer = "server_port: 1\ndatabase:\n  url: a\n  pool: 4\n";
+    let profile_buffer = "\nserver_port: -2\n";
+    std::fs::write(&base, base_buffer).unwrap();
+    std::fs::write(&profile, profile_buffer).unwrap();
+    let files = [base.clone(), profile.clone()];
+
+    let (path, message) = merged_error(&[(base_buffer, ConfigFormat::Yaml), (profile_buffer, ConfigFormat::Yaml)]);
+    assert_eq!(path, "server_port");
+    assert_eq!(
+      locate_error::<Located>(&files, &path, &message),
+      Some((profile.clone(), 2, 14))
+    );
+    assert_eq!(locate_error::<Located>(&files, "database.pool", &message), None);
+
+    let base_buffer = "server_port: 1\ndatabase:\n  url: a\n";
+    let profile_buffer = "server_port: 2\n";
+    std::fs::write(&base, base_buffer).unwrap();
+    std::fs::write(&profile, profile_buffer).unwrap();
+    let (path, message) = merged_error(&[(base_buffer, ConfigFormat::Yaml), (profile_buffer, ConfigFormat::Yaml)]);
+    assert_eq!((path.as_str(), message.as_str()), ("database", "missing field `pool`"));
+    assert_eq!(
+      locate_error::<Located>(&files, &path, &message).map(|(file, ..)| file),
+      Some(base.clone())
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// Creates an empty temporary directory for the test.
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cc-config-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn config_dirs_from_env_are_searched_first() {
+    let env = |pairs: &'static [(&'static str, &'static str)]| {
+      move |key: &str| {
+        pairs
+          .iter()
+          .find(|(name, _)| *name == key)
+          .map(|(_, value)| OsString::from(value))
+      }
+    };
+    let dirs = default_search_dirs(
+      "my-app",
+      env(&[
+        ("CC_MY_APP_CONFIG_DIRS", "/srv/a::/srv/b"),
+        ("XDG_CONFIG_HOME", "/xdg"),
+        ("HOME", "/home/user"),
+      ]),
+    );
+    assert_eq!(dirs, ["/srv/a", "/srv/b", "", "/xdg/my-app", "/etc"].map(PathBuf::from));
+
+    let dirs = default_search_dirs("my-app", env(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/user")]));
+    assert_eq!(dirs, ["", "/home/user/.config/my-app", "/etc"].map(PathBuf::from));
+
+    assert_eq!(default_search_dirs("my-app", env(&[])), ["", "/etc"].map(PathBuf::from));
+  }
+
+  #[test]
+  fn base_file_is_searched_in_order() {
+    let first = temp_dir("search-first");
+    let second = temp_dir("search-second");
+    let source = ConfigSource::new("app").search_dirs([&first, &second]);
+    assert_eq!(source.dirs(), [first.clone(), second.clone()]);
+    assert_eq!(source.find_base_file(), None);
+
+    std::fs::write(second.join("app.json"), "{}").unwrap();
+    std::fs::write(second.join("app.toml"), "").unwrap();
+    assert_eq!(source.find_base_file(), Some(second.join("app.toml")));
+
+    std::fs::write(first.join("app.yml"), "").unwrap();
+    assert_eq!(source.find_base_file(), Some(first.join("app.yml")));
+
+    let source = source.path(second.join("app.json"));
+    assert_eq!(source.find_base_file(), Some(second.join("app.json")));
+
+    let source = ConfigSource::new("app").search_dirs([&first]).add_search_dir(&second);
+    assert_eq!(source.dirs(), [first.clone(), second.clone()]);
+    std::fs::remove_dir_all(&first).unwrap();
+    std::fs::remove_dir_all(&second).unwrap();
+  }
+
+  #[test]
+  fn profile_file_is_found_next_to_the_base_file() {
+    let dir = temp_dir("profile");
+    std::fs::write(dir.join("config.toml"), "server_port = 1\nlog_level = \"info\"\n").unwrap();
+    std::fs::write(dir.join("config.prod.json"), r#"{ "server_port": 2 }"#).unwrap();
+    let source = ConfigSource::new("cc-config-test-app").path(dir.join("config.toml"));
+
+    let mut report = ConfigReport::default();
+    let (document, origin) = load_document(&source.clone().profile("prod"), &mut report);
+    assert!(report.is_ok());
+    assert_eq!(document, yaml("{ server_port: 2, log_level: info }"));
+    assert_eq!(origin.files, [dir.join("config.toml"), dir.join("config.prod.json")]);
+    assert_eq!(origin.profile.as_deref(), Some("prod"));
+
+    let mut report = ConfigReport::default();
+    let (document, _) = load_document(&source.profile("dev"), &mut report);
+    assert_eq!(document, yaml("{ server_port: 1, log_level: info }"));
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].file, Some(dir.join("config.dev.yaml")));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,16 +1,20 @@
 //! Setup module.
 
-use salvo::http::StatusCode;
-use serde::Deserialize;
 use serde::de::DeserializeOwned;
-use std::io::Read;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tracing_appender::non_blocking::WorkerGuard as TracingFileGuard;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::reload;
 
 use cc_utils::prelude::*;
 
-static E500: StatusCode = StatusCode::INTERNAL_SERVER_ERROR;
+use crate::config::{ConfigFormat, ConfigOrigin, ConfigSource, env_prefix};
+use crate::health::HealthChecks;
+use crate::shutdown::ShutdownHooks;
+use crate::startup::HostRouters;
 
 /// Provides at least values needed by Server Kit to start.
 pub trait GenericSetup {
@@ -20,51 +24,590 @@ pub trait GenericSetup {
   fn generic_values_mut(&mut self) -> &mut GenericValues;
 }
 
+/// Port the startup variants listen on if `server_port` isn't set.
+pub const DEFAULT_SERVER_PORT: u16 = 8800;
+/// Number of rotated log files kept if `log_rolling_max_files` isn't set.
+pub const DEFAULT_LOG_ROLLING_MAX_FILES: u32 = 5;
+/// Seconds `/readyz` waits for the readiness checks if `health_check_timeout` isn't set.
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT: u64 = 5;
+/// Path the metrics are served on if `metrics_path` isn't set.
+#[cfg(feature = "metrics")]
+pub const DEFAULT_METRICS_PATH: &str = "/metrics";
+/// Share of the traces sampled if `open_telemetry.sampling_ratio` isn't set.
+#[cfg(feature = "otel")]
+pub const DEFAULT_SAMPLING_RATIO: f64 = 1.0;
+/// Size of the batch exporters' queue if `open_telemetry.max_queue_size` isn't set.
+#[cfg(feature = "otel")]
+pub const DEFAULT_MAX_QUEUE_SIZE: usize = 2048;
+/// Interval between the batch exports, in milliseconds, if `open_telemetry.flush_interval` isn't set.
+#[cfg(feature = "otel")]
+pub const DEFAULT_FLUSH_INTERVAL: u64 = 5000;
+
+fn default_server_port() -> u16 {
+  DEFAULT_SERVER_PORT
+}
+
+/// Client certificate verification mode for mutual TLS.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ClientAuthMode {
+  /// Connections without a valid client certificate are rejected.
+  #[default]
+  Required,
+  /// Connections without a client certificate are accepted; the presented ones are verified anyway.
+  Optional,
+}
+
+/// Minimum TLS protocol version.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum TlsVersion {
+  /// TLS 1.2 and TLS 1.3 are accepted.
+  #[default]
+  #[serde(rename = "tls1.2")]
+  Tls12,
+  /// Only TLS 1.3 is accepted.
+  #[serde(rename = "tls1.3")]
+  Tls13,
+}
+
+/// TLS parameters, applied to every listener with SSL certificates.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TlsSettings {
+  /// Minimum TLS version: `tls1.2` (default) or `tls1.3`. QUIC always uses TLS 1.3.
+  #[serde(default)]
+  pub min_version: TlsVersion,
+  /// Allowed cipher suites, e.g. `TLS13_AES_256_GCM_SHA384`; if not set, all the suites of the crypto provider are
+  /// allowed.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub cipher_suites: Vec<String>,
+  /// ALPN protocols offered in addition to HTTP ones (`h2` and `http/1.1` for TCP, `h3` for QUIC).
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub alpn_protocols: Vec<String>,
+}
+
+fn default_hsts_max_age() -> u64 {
+  31_536_000
+}
+
+/// HTTP Strict Transport Security policy: tells the browsers to use only HTTPS for the domain.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HstsPolicy {
+  /// How long the browsers should remember the policy, in seconds; a year by default.
+  #[serde(default = "default_hsts_max_age")]
+  pub max_age: u64,
+  /// Apply the policy to all the subdomains too.
+  #[serde(default)]
+  pub include_subdomains: bool,
+  /// Allow the domain to be included in the browsers' preload lists.
+  #[serde(default)]
+  pub preload: bool,
+}
+
+impl Default for HstsPolicy {
+  fn default() -> Self {
+    Self {
+      max_age: default_hsts_max_age(),
+      include_subdomains: false,
+      preload: false,
+    }
+  }
+}
+
+impl HstsPolicy {
+  /// Returns `Strict-Transport-Security` header value.
+  pub fn header_value(&self) -> String {
+    let mut value = format!("max-age={}", self.max_age);
+    if self.include_subdomains {
+      value.push_str("; includeSubDomains");
+    }
+    if self.preload {
+      value.push_str("; preload");
+    }
+    value
+  }
+}
+
+/// Security headers preset.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityHeadersPreset {
+  /// For API-only services: nothing may be loaded or framed, no referrer is sent.
+  Api,
+  /// For browser-facing services: the resources of the same origin are allowed, the referrer is sent to the same
+  /// origin only, sensitive browser features are disabled.
+  Browser,
+}
+
+/// Security headers added to every response.
+///
+/// The headers which aren't set are taken from the preset, if any; set a header to an empty string to not send it.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SecurityHeaders {
+  /// Preset: `api` or `browser`.
+  pub preset: Option<SecurityHeadersPreset>,
+  /// HSTS policy, sent with the HTTPS and QUIC responses only; the presets use the default policy.
+  pub hsts: Option<HstsPolicy>,
+  /// `Content-Security-Policy` header.
+  pub content_security_policy: Option<String>,
+  /// `X-Frame-Options` header: `DENY` or `SAMEORIGIN`.
+  pub frame_options: Option<String>,
+  /// `Referrer-Policy` header, e.g. `no-referrer`.
+  pub referrer_policy: Option<String>,
+  /// `Permissions-Policy` header, e.g. `camera=(), microphone=()`.
+  pub permissions_policy: Option<String>,
+}
+
+impl SecurityHeaders {
+  /// Returns the HSTS policy to apply.
+  pub fn hsts_policy(&self) -> Option<HstsPolicy> {
+    self.hsts.clone().or_else(|| self.preset.map(|_| HstsPolicy::default()))
+  }
+
+  /// Returns the headers to add to every response except HSTS, with the preset applied; `X-Content-Type-Options:
+  /// nosniff` is always added.
+  pub fn headers(&self) -> Vec<(&'static str, String)> {
+    let preset = |api: &str, browser: &str| match self.preset {
+      Some(SecurityHeadersPreset::Api) => Some(api.to_string()),
+      Some(SecurityHeadersPreset::Browser) => Some(browser.to_string()),
+      None => None,
+    };
+    [
+      ("x-content-type-options", Some("nosniff".to_string())),
+      (
+        "content-security-policy",
+        self.content_security_policy.clone().or_else(|| {
+          preset(
+            "default-src 'none'; frame-ancestors 'none'",
+            "default-src 'self'; object-src 'none'; base-uri 'self'; frame-ancestors 'self'",
+          )
+        }),
+      ),
+      (
+        "x-frame-options",
+        self.frame_options.clone().or_else(|| preset("DENY", "SAMEORIGIN")),
+      ),
+      (
+        "referrer-policy",
+        self
+          .referrer_policy
+          .clone()
+          .or_else(|| preset("no-referrer", "strict-origin-when-cross-origin")),
+      ),
+      (
+        "permissions-policy",
+        self
+          .permissions_policy
+          .clone()
+          .or_else(|| preset("", "camera=(), microphone=(), geolocation=(), payment=(), usb=()")),
+      ),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value.filter(|value| !value.is_empty())?)))
+    .collect()
+  }
+}
+
+/// Certificate of a virtual host, selected by the server name the client asks for (SNI).
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HostCert {
+  /// Path to SSL key.
+  pub ssl_key_path: String,
+  /// Path to SSL certificate.
+  pub ssl_crt_path: String,
+}
+
+#[cfg(feature = "otel")]
+/// OTLP transport.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum OtlpProtocol {
+  /// gRPC, usually on port 4317.
+  #[default]
+  #[serde(rename = "grpc")]
+  Grpc,
+  /// HTTP with protobuf payloads, usually on port 4318.
+  #[serde(rename = "http/protobuf")]
+  HttpProtobuf,
+}
+
+#[cfg(feature = "otel")]
+/// OpenTelemetry export parameters.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct OpenTelemetrySettings {
+  /// Transport: `grpc` (default) or `http/protobuf`. For HTTP, `open_telemetry_endpoint` is the base URL, e.g.
+  /// `http://localhost:4318`, and the signals are sent to `/v1/traces`, `/v1/metrics` and `/v1/logs`.
+  #[serde(default)]
+  pub protocol: OtlpProtocol,
+  /// Headers sent with every export, e.g. the collector's API key.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, String>,
+  /// Resource attributes in addition to `service.name`, e.g. `deployment.environment` and `service.version`.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub resource_attributes: BTreeMap<String, String>,
+  /// Share of the traces to sample, from 0 to 1; the spans with a remote parent follow its decision. 1 by default.
+  pub sampling_ratio: Option<f64>,
+  /// Set this to `false` to export every span and log event as soon as it ends, instead of in batches.
+  pub batch: Option<bool>,
+  /// Spans or log events the batch exporter may keep; the new ones are dropped when the queue is full. 2048 by
+  /// default.
+  pub max_queue_size: Option<usize>,
+  /// Interval between the batch exports, in milliseconds; 5000 by default.
+  pub flush_interval: Option<u64>,
+}
+
 /// Server startup variants.
 ///
 /// These are the hardcoded variants; by default, `salvo` can much more than this.
-#[derive(Clone, Eq, PartialEq)]
+///
+/// The variant is selected by `startup_type` key and carries all the fields it needs, so the misconfigured file
+/// fails to parse with the missing field named.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "startup_type", rename_all = "snake_case")]
 pub enum StartupVariant {
   /// Will listen `http://127.0.0.1:{port}` only.
-  HttpLocalhost,
+  HttpLocalhost {
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+  },
   /// Will listen `http://{host}:{port}`. Not recommended.
-  UnsafeHttp,
+  UnsafeHttp {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+  },
   #[cfg(feature = "acme")]
   /// Will listen `https://{host}:{port}` with automatic SSL certificate acquiring.
-  HttpsAcme,
+  HttpsAcme {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+    /// ACME origin; see [`salvo/conn/acme` docs](https://docs.rs/salvo/latest/salvo/conn/acme/index.html).
+    acme_domain: String,
+  },
   #[cfg(all(feature = "http3", feature = "acme"))]
   /// Will listen `https|quic://{host}:{port}` with automatic SSL certificate acquiring.
-  QuinnAcme,
+  QuinnAcme {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+    /// ACME origin; see [`salvo/conn/acme` docs](https://docs.rs/salvo/latest/salvo/conn/acme/index.html).
+    acme_domain: String,
+  },
   /// Will listen `https://{host}:{port}` with your SSL cert and key.
-  HttpsOnly,
+  HttpsOnly {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+    /// Path to SSL key.
+    ssl_key_path: String,
+    /// Path to SSL certificate.
+    ssl_crt_path: String,
+    /// Path to CA bundle to verify the client certificates with (mutual TLS); clients aren't asked for certificates
+    /// if not set.
+    ssl_client_ca_path: Option<String>,
+    /// Client certificate verification mode: `required` (default) or `optional`.
+    #[serde(default)]
+    ssl_client_auth: ClientAuthMode,
+    /// Certificates of the virtual hosts by their names; other hosts get `ssl_crt_path` certificate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ssl_hosts: BTreeMap<String, HostCert>,
+  },
   #[cfg(feature = "http3")]
   /// Will listen `https|quic://{host}:{port}` with your SSL cert and key.
-  Quinn,
+  Quinn {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+    /// Path to SSL key.
+    ssl_key_path: String,
+    /// Path to SSL certificate.
+    ssl_crt_path: String,
+    /// Path to CA bundle to verify the client certificates with (mutual TLS); clients aren't asked for certificates
+    /// if not set.
+    ssl_client_ca_path: Option<String>,
+    /// Client certificate verification mode: `required` (default) or `optional`.
+    #[serde(default)]
+    ssl_client_auth: ClientAuthMode,
+    /// Certificates of the virtual hosts by their names; other hosts get `ssl_crt_path` certificate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ssl_hosts: BTreeMap<String, HostCert>,
+  },
   #[cfg(feature = "http3")]
   /// Will listen `quic://{host}:{port}` only, with your SSL cert and key.
-  QuinnOnly,
+  QuinnOnly {
+    /// Server host, e.g. `0.0.0.0`.
+    server_host: String,
+    /// Server port.
+    #[serde(default = "default_server_port")]
+    server_port: u16,
+    /// Path to SSL key.
+    ssl_key_path: String,
+    /// Path to SSL certificate.
+    ssl_crt_path: String,
+    /// Path to CA bundle to verify the client certificates with (mutual TLS); clients aren't asked for certificates
+    /// if not set.
+    ssl_client_ca_path: Option<String>,
+    /// Client certificate verification mode: `required` (default) or `optional`.
+    #[serde(default)]
+    ssl_client_auth: ClientAuthMode,
+    /// Certificates of the virtual hosts by their names; other hosts get `ssl_crt_path` certificate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    ssl_hosts: BTreeMap<String, HostCert>,
+  },
+  #[cfg(unix)]
+  /// Will listen Unix domain socket, e.g. behind local nginx or envoy.
+  UnixSocket {
+    /// Path to the socket file.
+    socket_path: PathBuf,
+    /// Socket file mode in octal, e.g. `"660"`.
+    socket_mode: Option<String>,
+    /// Socket file owner: `user`, `user:group` or `:group`; names or numeric IDs.
+    socket_owner: Option<String>,
+  },
+  #[cfg(unix)]
+  /// Will listen the socket passed by systemd socket activation (`LISTEN_FDS`), TCP or Unix one.
+  SystemdSocket {
+    /// Name of the socket (`FileDescriptorName=` in the socket unit); the first passed socket is used if not set.
+    listen_fd_name: Option<String>,
+  },
+}
+
+impl Default for StartupVariant {
+  fn default() -> Self {
+    Self::HttpLocalhost {
+      server_port: default_server_port(),
+    }
+  }
+}
+
+impl StartupVariant {
+  /// Host to listen; `None` for Unix and systemd sockets.
+  pub fn server_host(&self) -> Option<&str> {
+    match self {
+      Self::HttpLocalhost { .. } => Some("127.0.0.1"),
+      Self::UnsafeHttp { server_host, .. } | Self::HttpsOnly { server_host, .. } => Some(server_host),
+      #[cfg(feature = "acme")]
+      Self::HttpsAcme { server_host, .. } => Some(server_host),
+      #[cfg(all(feature = "http3", feature = "acme"))]
+      Self::QuinnAcme { server_host, .. } => Some(server_host),
+      #[cfg(feature = "http3")]
+      Self::Quinn { server_host, .. } | Self::QuinnOnly { server_host, .. } => Some(server_host),
+      #[cfg(unix)]
+      Self::UnixSocket { .. } | Self::SystemdSocket { .. } => None,
+    }
+  }
+
+  /// Port to listen; `None` for Unix and systemd sockets.
+  pub fn server_port(&self) -> Option<u16> {
+    self.server_port_ref().copied()
+  }
+
+  /// Sets the port to listen, e.g. from `server_port_achiever`; does nothing for Unix and systemd sockets.
+  pub fn set_server_port(&mut self, port: u16) {
+    if let Some(server_port) = self.server_port_mut() {
+      *server_port = port;
+    }
+  }
+
+  fn server_port_ref(&self) -> Option<&u16> {
+    match self {
+      Self::HttpLocalhost { server_port }
+      | Self::UnsafeHttp { server_port, .. }
+      | Self::HttpsOnly { server_port, .. } => Some(server_port),
+      #[cfg(feature = "acme")]
+      Self::HttpsAcme { server_port, .. } => Some(server_port),
+      #[cfg(all(feature = "http3", feature = "acme"))]
+      Self::QuinnAcme { server_port, .. } => Some(server_port),
+      #[cfg(feature = "http3")]
+      Self::Quinn { server_port, .. } | Self::QuinnOnly { server_port, .. } => Some(server_port),
+      #[cfg(unix)]
+      Self::UnixSocket { .. } | Self::SystemdSocket { .. } => None,
+    }
+  }
+
+  fn server_port_mut(&mut self) -> Option<&mut u16> {
+    match self {
+      Self::HttpLocalhost { server_port }
+      | Self::UnsafeHttp { server_port, .. }
+      | Self::HttpsOnly { server_port, .. } => Some(server_port),
+      #[cfg(feature = "acme")]
+      Self::HttpsAcme { server_port, .. } => Some(server_port),
+      #[cfg(all(feature = "http3", feature = "acme"))]
+      Self::QuinnAcme { server_port, .. } => Some(server_port),
+      #[cfg(feature = "http3")]
+      Self::Quinn { server_port, .. } | Self::QuinnOnly { server_port, .. } => Some(server_port),
+      #[cfg(unix)]
+      Self::UnixSocket { .. } | Self::SystemdSocket { .. } => None,
+    }
+  }
+
+  /// Address to listen: `{host}:{port}`, `unix:{path}` for Unix socket or `systemd:{name}` for socket activation.
+  pub fn socket_addr(&self) -> String {
+    #[cfg(unix)]
+    match self {
+      Self::UnixSocket { socket_path, .. } => return format!("unix:{}", socket_path.display()),
+      Self::SystemdSocket { listen_fd_name } => {
+        return format!("systemd:{}", listen_fd_name.as_deref().unwrap_or_default());
+      }
+      _ => {}
+    }
+    format!(
+      "{}:{}",
+      self.server_host().unwrap_or_default(),
+      self.server_port().unwrap_or_default()
+    )
+  }
+
+  /// Returns `true` if the server listens QUIC and should advertise it with `Alt-Svc` header.
+  pub fn is_quic(&self) -> bool {
+    match self {
+      #[cfg(all(feature = "http3", feature = "acme"))]
+      Self::QuinnAcme { .. } => true,
+      #[cfg(feature = "http3")]
+      Self::Quinn { .. } | Self::QuinnOnly { .. } => true,
+      _ => false,
+    }
+  }
+
+  /// Returns `true` if the server listens HTTPS or QUIC.
+  pub fn is_tls(&self) -> bool {
+    match self {
+      Self::HttpsOnly { .. } => true,
+      #[cfg(feature = "acme")]
+      Self::HttpsAcme { .. } => true,
+      _ => self.is_quic(),
+    }
+  }
+
+  /// Returns `true` if the server listens HTTPS over TCP, so the browsers can be redirected to it.
+  pub fn is_https(&self) -> bool {
+    match self {
+      #[cfg(feature = "http3")]
+      Self::QuinnOnly { .. } => false,
+      _ => self.is_tls(),
+    }
+  }
+
+  /// Returns `true` if the listener verifies the client certificates (`ssl_client_ca_path` is set).
+  pub fn verifies_clients(&self) -> bool {
+    match self {
+      Self::HttpsOnly { ssl_client_ca_path, .. } => ssl_client_ca_path.is_some(),
+      #[cfg(feature = "http3")]
+      Self::Quinn { ssl_client_ca_path, .. } | Self::QuinnOnly { ssl_client_ca_path, .. } => {
+        ssl_client_ca_path.is_some()
+      }
+      _ => false,
+    }
+  }
+
+  /// Returns the virtual hosts with their own certificates, `ssl_hosts`.
+  pub fn ssl_hosts(&self) -> Option<&BTreeMap<String, HostCert>> {
+    match self {
+      Self::HttpsOnly { ssl_hosts, .. } => Some(ssl_hosts),
+      #[cfg(feature = "http3")]
+      Self::Quinn { ssl_hosts, .. } | Self::QuinnOnly { ssl_hosts, .. } => Some(ssl_hosts),
+      _ => None,
+    }
+  }
+
+  /// Returns the certificate, the key and the client CA bundle files by their fields' names.
+  pub(crate) fn ssl_files(&self) -> Vec<(&'static str, &str)> {
+    let (ssl_crt_path, ssl_key_path, ssl_client_ca_path) = match self {
+      Self::HttpsOnly {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ..
+      } => (ssl_crt_path, ssl_key_path, ssl_client_ca_path),
+      #[cfg(feature = "http3")]
+      Self::Quinn {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ..
+      }
+      | Self::QuinnOnly {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ..
+      } => (ssl_crt_path, ssl_key_path, ssl_client_ca_path),
+      _ => return Vec::new(),
+    };
+    let mut files = vec![
+      ("ssl_crt_path", ssl_crt_path.as_str()),
+      ("ssl_key_path", ssl_key_path.as_str()),
+    ];
+    files.extend(ssl_client_ca_path.as_deref().map(|path| ("ssl_client_ca_path", path)));
+    files
+  }
+
+  /// Returns `true` if the certificate is obtained with ACME.
+  pub fn is_acme(&self) -> bool {
+    match self {
+      #[cfg(feature = "acme")]
+      Self::HttpsAcme { .. } => true,
+      #[cfg(all(feature = "http3", feature = "acme"))]
+      Self::QuinnAcme { .. } => true,
+      _ => false,
+    }
+  }
 }
 
 /// Server generic configuration.
-#[derive(Clone, Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GenericValues {
   /// Application name.
   ///
   /// You're not needed to write it in YAML configuration, instead you should send it to `load_generic_config` function.
   #[serde(skip)]
   pub app_name: String,
-  /// Startup variant. Converts to `StartupVariant`.
-  pub startup_type: String,
-  /// Server host.
-  pub server_host: Option<String>,
-  /// Server port. For no reverse proxy and Internet usage, set to `80` for HTTP and `443` for HTTPS/QUIC.
-  pub server_port: Option<u16>,
-  /// ACME origin; see [`salvo/conn/acme` docs](https://docs.rs/salvo/latest/salvo/conn/acme/index.html).
-  pub acme_domain: Option<String>,
-  /// Path to SSL key.
-  pub ssl_key_path: Option<String>,
-  /// Path to SSL certificate.
-  pub ssl_crt_path: Option<String>,
+  /// Where the configuration came from: the file and the environment variables which have overridden it.
+  #[serde(skip)]
+  pub config_origin: ConfigOrigin,
+  /// Startup variant, selected by `startup_type` key, with its host, port, SSL or ACME settings.
+  ///
+  /// For no reverse proxy and Internet usage, set `server_port` to `80` for HTTP and `443` for HTTPS/QUIC.
+  #[serde(flatten)]
+  pub startup_variant: StartupVariant,
+  /// Additional listeners serving the same routes, e.g. a localhost plain-HTTP one for sidecars next to the public
+  /// HTTPS one. Each item is written like the startup variant itself: `startup_type` with its own fields.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub listeners: Vec<StartupVariant>,
+  /// Listener for the kit's internal endpoints (health, OpenAPI docs), usually on localhost; written like the startup
+  /// variant itself, e.g. `{ startup_type: http_localhost, server_port: 9090 }`. If set, these endpoints are served
+  /// only there instead of the main listeners.
+  pub admin: Option<StartupVariant>,
+  /// TLS parameters of the listeners with SSL certificates: minimum version, cipher suites and ALPN protocols.
+  #[serde(default)]
+  pub tls: TlsSettings,
+  #[cfg(feature = "force-https")]
+  /// Port to listen plain HTTP on and redirect all the requests to HTTPS, e.g. `80`.
+  pub redirect_http_port: Option<u16>,
+  /// Security headers: HSTS, `Content-Security-Policy`, `X-Frame-Options`, `Referrer-Policy` and
+  /// `Permissions-Policy`, with presets for API-only and browser-facing services.
+  pub security_headers: Option<SecurityHeaders>,
   /// If you want to run any migration or anything else just before server's start, set to path to binary.
   pub auto_migrate_bin: Option<String>,
   /// Use text file to find out which port to listen to.
@@ -102,18 +645,54 @@ pub struct GenericValues {
   #[cfg(feature = "otel")]
   /// Endpoint to export OpenTelemetry (e.g., Jaeger).
   pub open_telemetry_endpoint: Option<String>,
+  #[cfg(feature = "otel")]
+  /// OpenTelemetry export parameters: transport, headers, resource attributes, sampling and batching.
+  #[serde(default)]
+  pub open_telemetry: OpenTelemetrySettings,
+  #[cfg(all(feature = "otel", feature = "metrics"))]
+  /// Set this to `true` to export the metrics to `open_telemetry_endpoint` as well.
+  pub open_telemetry_metrics: Option<bool>,
+  #[cfg(feature = "otel")]
+  /// Set this to `true` to export the log events to `open_telemetry_endpoint` as well.
+  pub open_telemetry_logs: Option<bool>,
+
+  /// Set this to `true` to serve `/healthz` and `/readyz` endpoints; see `HealthCheck`.
+  pub allow_health_endpoints: Option<bool>,
+  /// Time the readiness checks may take, in seconds; 5 by default.
+  pub health_check_timeout: Option<u64>,
+
+  #[cfg(feature = "metrics")]
+  /// Set this to `true` to record the HTTP, runtime and process metrics and serve them in Prometheus format.
+  pub allow_metrics: Option<bool>,
+  #[cfg(feature = "metrics")]
+  /// Path of the metrics endpoint; `/metrics` by default.
+  pub metrics_path: Option<String>,
+
+  /// Time to keep serving after the shutdown signal before stopping the listeners, in seconds, so the load balancer
+  /// can deregister the server.
+  pub pre_stop_delay: Option<u64>,
+  /// Time to wait for the running requests after the listeners have stopped, in seconds; the remaining connections
+  /// are closed after it. Not limited by default.
+  pub shutdown_grace_period: Option<u64>,
+
+  /// Set this to `true` to re-read the configuration files on change.
+  ///
+  /// Log levels, CORS domain and your own fields are applied live; see `watch_generic_config`.
+  pub hot_reload: Option<bool>,
 }
 
 impl Default for GenericValues {
   fn default() -> Self {
     Self {
       app_name: "generic".into(),
-      startup_type: "http_localhost".into(),
-      server_host: None,
-      server_port: Some(8800),
-      acme_domain: None,
-      ssl_key_path: None,
-      ssl_crt_path: None,
+      config_origin: ConfigOrigin::default(),
+      startup_variant: StartupVariant::default(),
+      listeners: vec![],
+      admin: None,
+      tls: TlsSettings::default(),
+      #[cfg(feature = "force-https")]
+      redirect_http_port: None,
+      security_headers: None,
       auto_migrate_bin: None,
       #[cfg(feature = "cors")]
       allow_cors_domain: None,
@@ -133,8 +712,72 @@ impl Default for GenericValues {
       log_rolling_max_files: None,
       #[cfg(feature = "otel")]
       open_telemetry_endpoint: None,
+      #[cfg(feature = "otel")]
+      open_telemetry: OpenTelemetrySettings::default(),
+      #[cfg(all(feature = "otel", feature = "metrics"))]
+      open_telemetry_metrics: None,
+      #[cfg(feature = "otel")]
+      open_telemetry_logs: None,
       server_port_achiever: None,
+      allow_health_endpoints: None,
+      health_check_timeout: None,
+      #[cfg(feature = "metrics")]
+      allow_metrics: None,
+      #[cfg(feature = "metrics")]
+      metrics_path: None,
+      pre_stop_delay: None,
+      shutdown_grace_period: None,
+      hot_reload: None,
+    }
+  }
+}
+
+impl GenericValues {
+  /// Returns the values with the kit's defaults filled in where nothing is set, as the server applies them; used by
+  /// `print-config`.
+  pub fn with_defaults(mut self) -> Self {
+    if self.log_level.is_none() && cfg!(debug_assertions) {
+      self.log_level = Some("debug".into());
+    }
+    self.log_rolling.get_or_insert_with(|| "never".into());
+    self.log_rolling_max_files.get_or_insert(DEFAULT_LOG_ROLLING_MAX_FILES);
+    self.allow_health_endpoints.get_or_insert(false);
+    self.health_check_timeout.get_or_insert(DEFAULT_HEALTH_CHECK_TIMEOUT);
+    self.pre_stop_delay.get_or_insert(0);
+    self.hot_reload.get_or_insert(false);
+    #[cfg(feature = "oapi")]
+    {
+      self.allow_oapi_access.get_or_insert(false);
+      self.oapi_name.get_or_insert_with(|| self.app_name.clone());
+    }
+    #[cfg(feature = "metrics")]
+    {
+      self.allow_metrics.get_or_insert(false);
+      self.metrics_path.get_or_insert_with(|| DEFAULT_METRICS_PATH.into());
+    }
+    #[cfg(feature = "otel")]
+    {
+      self.open_telemetry_logs.get_or_insert(false);
+      self.open_telemetry.sampling_ratio.get_or_insert(DEFAULT_SAMPLING_RATIO);
+      self.open_telemetry.batch.get_or_insert(true);
+      self.open_telemetry.max_queue_size.get_or_insert(DEFAULT_MAX_QUEUE_SIZE);
+      self.open_telemetry.flush_interval.get_or_insert(DEFAULT_FLUSH_INTERVAL);
     }
+    #[cfg(all(feature = "otel", feature = "metrics"))]
+    self.open_telemetry_metrics.get_or_insert(false);
+    self
+  }
+}
+
+#[cfg(feature = "metrics")]
+impl GenericValues {
+  /// Returns `true` if the metrics are served in Prometheus format or exported to OpenTelemetry.
+  pub fn records_metrics(&self) -> bool {
+    #[cfg(feature = "otel")]
+    if self.open_telemetry_metrics.is_some_and(|v| v) && self.open_telemetry_endpoint.is_some() {
+      return true;
+    }
+    self.allow_metrics.is_some_and(|v| v)
   }
 }
 
@@ -143,8 +786,77 @@ impl Default for GenericValues {
 pub struct GenericServerState {
   /// Converted startup variant, ready to launch.
   pub startup_variant: StartupVariant,
+  /// Additional listeners serving the same routes.
+  pub listeners: Vec<StartupVariant>,
   /// File log guard; needed to be handled the entire time the application is running.
   pub _file_log_guard: Option<Arc<TracingFileGuard>>,
+  /// Handles to change the log levels while the server is running.
+  pub log_levels: LogLevelHandles,
+  /// Current generic values; updated by `watch_generic_config` when the configuration is reloaded.
+  pub live_values: Arc<tokio::sync::watch::Sender<GenericValues>>,
+  /// Readiness checks served by `/readyz`.
+  pub health_checks: HealthChecks,
+  /// Routers of the virtual hosts, mounted by `get_root_router`.
+  pub host_routers: HostRouters,
+  /// Hooks to run after all the listeners have stopped, before the server's future resolves.
+  pub shutdown_hooks: ShutdownHooks,
+}
+
+type LevelSetter = Arc<dyn Fn(LevelFilter) -> bool + Send + Sync>;
+
+/// Handles to change the log levels while the server is running.
+///
+/// Only the outputs which were enabled at startup can be changed; enabling new ones requires restart.
+#[derive(Clone, Default)]
+pub struct LogLevelHandles {
+  stdout: Vec<LevelSetter>,
+  file: Option<LevelSetter>,
+}
+
+impl LogLevelHandles {
+  /// Changes the level of logging to stdout (and to OpenTelemetry, if enabled).
+  pub fn set_log_level(&self, level: LevelFilter) -> MResult<()> {
+    if self.stdout.is_empty() {
+      return Err(
+        ErrorResponse::from("Logging to stdout was disabled at startup; restart the server to enable it.")
+          .with_500_pub()
+          .build(),
+      );
+    }
+    for setter in &self.stdout {
+      if !setter(level) {
+        return Err(
+          ErrorResponse::from("Failed to change the log level.")
+            .with_500_pub()
+            .build(),
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Changes the level of logging to file.
+  pub fn set_log_file_level(&self, level: LevelFilter) -> MResult<()> {
+    let Some(setter) = &self.file else {
+      return Err(
+        ErrorResponse::from("Logging to file was disabled at startup; restart the server to enable it.")
+          .with_500_pub()
+          .build(),
+      );
+    };
+    if !setter(level) {
+      return Err(
+        ErrorResponse::from("Failed to change the log level.")
+          .with_500_pub()
+          .build(),
+      );
+    }
+    Ok(())
+  }
+}
+
+fn level_setter<S: 'static>(handle: reload::Handle<LevelFilter, S>) -> LevelSetter {
+  Arc::new(move |level| handle.reload(level).is_ok())
 }
 
 async fn watcher<P: AsRef<std::path::Path>>(path: P) -> MResult<u16> {
@@ -180,60 +892,39 @@ async fn watcher<P: AsRef<std::path::Path>>(path: P) -> MResult<u16> {
   Err(ErrorResponse::from("Event channel is broken!").with_500_pub().build())
 }
 
-/// Loads the config from YAML file (`{app_name}.yaml`).
+/// Loads the config from `{app_name}.yaml` (or `.yml`, `.toml`, `.json`) found in the search directories.
+///
+/// The profile is taken from `CC_{APP_NAME}_PROFILE` environment variable; see `load_generic_config_with_profile`.
+/// To load the file from a custom location, use `ConfigSource`.
+///
+/// Any value can be overridden by environment variable `CC_{APP_NAME}_{KEY}`, e.g. `CC_MY_APP_SERVER_PORT=8080`.
+/// Nested keys of your `GenericSetup` type are separated with double underscore: `CC_MY_APP_DATABASE__URL`.
+/// Environment variables take precedence over the files.
 pub async fn load_generic_config<T: DeserializeOwned + GenericSetup + Default>(app_name: &str) -> MResult<T> {
-  let mut file = std::fs::File::open(format!("{}.yaml", app_name));
-  if file.is_err() {
-    file = std::fs::File::open(format!("/etc/{}.yaml", app_name));
-  }
-  let mut file = file.consider(Some(E500), Some("The server configuration could not be found."), true)?;
-
-  let mut buffer = String::new();
-  file.read_to_string(&mut buffer).consider(
-    Some(E500),
-    Some("Failed to read the contents of the server configuration file."),
-    true,
-  )?;
-  let mut config: T = serde_yaml::from_str(&buffer).map_err(|_| {
-    ErrorResponse::from("Failed to parse the contents of the server configuration file.")
-      .with_500_pub()
-      .build()
-  })?;
-
-  let data = config.generic_values_mut();
-  data.app_name = app_name.to_string();
+  load_generic_config_with_profile(app_name, None).await
+}
 
-  #[cfg(feature = "oapi")]
-  if data.allow_oapi_access.is_some_and(|v| v) {
-    if data.oapi_name.is_none() {
-      return Err(
-        ErrorResponse::from("The API name for OAPI is not specified.")
-          .with_500_pub()
-          .build(),
-      );
-    }
-    if data.oapi_ver.is_none() {
-      return Err(
-        ErrorResponse::from("The API version for OAPI is not specified.")
-          .with_500_pub()
-          .build(),
-      );
-    }
-    if data.oapi_api_addr.is_none() {
-      return Err(
-        ErrorResponse::from("The path to OAPI was not specified.")
-          .with_500_pub()
-          .build(),
-      );
-    }
-  }
+/// Loads the config from `{app_name}.yaml` merged with the profile's file (`{app_name}.{profile}.yaml`).
+///
+/// Both files can pull in shared fragments with `include:` key (a path or a list of paths relative to the file).
+/// The documents are deep-merged before the deserialization, so your own fields get profiles too.
+///
+/// The whole configuration is checked before returning, and the error lists all the problems found;
+/// see `check_generic_config`.
+pub async fn load_generic_config_with_profile<T: DeserializeOwned + GenericSetup + Default>(
+  app_name: &str,
+  profile: Option<&str>,
+) -> MResult<T> {
+  ConfigSource::with_profile(app_name, profile).load().await
+}
 
+/// Replaces the server port with the one from `server_port_achiever` file, if it is set.
+pub(crate) async fn achieve_server_port(data: &mut GenericValues) -> MResult<()> {
   if let Some(achiever) = &data.server_port_achiever {
     let port = watcher(achiever.as_path()).await?;
-    data.server_port = Some(port);
+    data.startup_variant.set_server_port(port);
   }
-
-  Ok(config)
+  Ok(())
 }
 
 /// Loads the server's state: initializes the logging and checks YAML config for misconfigurations and errors.
@@ -244,65 +935,82 @@ pub async fn load_generic_state<T: GenericSetup>(setup: &T) -> MResult<GenericSe
   let log_file_level = match_log_level(&data.log_file_level);
   let log_rolling = match_log_file_rolling(&data.log_rolling)?;
 
-  let file_log_guard = init_logging(
+  let shutdown_hooks = ShutdownHooks::default();
+  let (file_log_guard, log_levels) = init_logging(
     &setup.generic_values().app_name,
     &log_level,
     &log_file_level,
     log_rolling,
     &data.log_rolling_max_files,
     #[cfg(feature = "otel")]
-    &data.open_telemetry_endpoint,
+    data,
+    #[cfg(feature = "otel")]
+    &shutdown_hooks,
   )?;
 
+  log_config_origin(data);
+
+  #[cfg(feature = "metrics")]
+  if data.records_metrics() {
+    crate::metrics::init_metrics(data)?;
+    shutdown_hooks.register(crate::metrics::shutdown_metrics());
+  }
+
   let state = GenericServerState {
-    startup_variant: match &*data.startup_type {
-      "http_localhost" => {
-        if data.server_host.is_some() { return Err(ErrorResponse::from("Server will only listen `127.0.0.1` address because of `http_localhost` startup variant. Consider to move to `https_only` or `quinn`.").with_500_pub().build()) }
-        StartupVariant::HttpLocalhost
-      },
-      "unsafe_http" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        StartupVariant::UnsafeHttp
-      }
-      #[cfg(feature = "acme")]
-      "https_acme" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        if data.acme_domain.is_none() { return Err(ErrorResponse::from("Choose ACME's domain!").with_500_pub().build()) }
-        StartupVariant::HttpsAcme
-      },
-      "https_only" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        if data.ssl_key_path.is_none() { return Err(ErrorResponse::from("Choose SSL key path.").with_500_pub().build()) }
-        if data.ssl_crt_path.is_none() { return Err(ErrorResponse::from("Choose SSL cert path.").with_500_pub().build()) }
-        StartupVariant::HttpsOnly
-      },
-      #[cfg(all(feature = "http3", feature = "acme"))]
-      "quinn_acme" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        if data.acme_domain.is_none() { return Err(ErrorResponse::from("Choose ACME's domain!").with_500_pub().build()) }
-        StartupVariant::QuinnAcme
-      },
-      #[cfg(feature = "http3")]
-      "quinn" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        if data.ssl_key_path.is_none() { return Err(ErrorResponse::from("Choose SSL key path.").with_500_pub().build()) }
-        if data.ssl_crt_path.is_none() { return Err(ErrorResponse::from("Choose SSL cert path.").with_500_pub().build()) }
-        StartupVariant::Quinn
-      },
-      #[cfg(feature = "http3")]
-      "quinn_only" => {
-        if data.server_host.is_none() { return Err(ErrorResponse::from("Choose server's host, e.g. `0.0.0.0`.").with_500_pub().build()) }
-        if data.ssl_key_path.is_none() { return Err(ErrorResponse::from("Choose SSL key path.").with_500_pub().build()) }
-        if data.ssl_crt_path.is_none() { return Err(ErrorResponse::from("Choose SSL cert path.").with_500_pub().build()) }
-        StartupVariant::QuinnOnly
-      },
-      _ => return Err(ErrorResponse::from("The server deployment method could not be determined. Read the documentation on the `startup_variant` field.").with_500_pub().build()),
-    },
+    startup_variant: data.startup_variant.clone(),
+    listeners: data.listeners.clone(),
     _file_log_guard: file_log_guard.map(Arc::new),
+    log_levels,
+    live_values: Arc::new(tokio::sync::watch::Sender::new(data.clone())),
+    health_checks: HealthChecks::default(),
+    host_routers: HostRouters::default(),
+    shutdown_hooks,
   };
   Ok(state)
 }
 
+fn log_config_origin(data: &GenericValues) {
+  let origin = &data.config_origin;
+  if let Some(base_file) = &origin.base_file {
+    tracing::info!(
+      "Configuration is loaded from `{}` ({:?})",
+      base_file.display(),
+      ConfigFormat::from_path(base_file)
+    );
+  }
+  let files = origin
+    .files
+    .iter()
+    .rev()
+    .map(|file| format!("`{}`", file.display()))
+    .collect::<Vec<_>>();
+  tracing::info!(
+    "Configuration precedence: environment variables `{}*` > {}",
+    env_prefix(&data.app_name),
+    if files.is_empty() {
+      "defaults".into()
+    } else {
+      files.join(" > ")
+    },
+  );
+  if let Some(profile) = &origin.profile {
+    tracing::info!("Configuration profile: `{}`", profile);
+  }
+  for var in &origin.env_overrides {
+    tracing::info!("Configuration value is overridden by `{}`", var);
+  }
+  for path in origin.secrets.paths() {
+    tracing::info!("Configuration value `{}` is resolved from a secret reference", path);
+  }
+}
+
+/// Converts the log level from the configuration to the filter; disabled logging is `LevelFilter::OFF`.
+pub(crate) fn log_level_filter(log_level: &Option<String>) -> LevelFilter {
+  match_log_level(log_level)
+    .map(LevelFilter::from_level)
+    .unwrap_or(LevelFilter::OFF)
+}
+
 fn match_log_level(log_level: &Option<String>) -> MResult<tracing::Level> {
   if log_level.is_some() {
     Ok(match log_level.as_ref().unwrap().as_str() {
@@ -342,6 +1050,33 @@ fn match_log_file_rolling(log_rolling: &Option<String>) -> MResult<tracing_appen
   }
 }
 
+#[cfg(any(feature = "otel", feature = "metrics"))]
+/// Returns OpenTelemetry resource shared by the traces, the metrics and the logs.
+pub(crate) fn otel_resource(
+  app_name: &str,
+  #[cfg(feature = "otel")] attributes: &BTreeMap<String, String>,
+) -> opentelemetry_sdk::Resource {
+  use opentelemetry::KeyValue;
+
+  #[allow(unused_mut)]
+  let mut resource = vec![KeyValue::new("service.name", app_name.to_owned())];
+  #[cfg(feature = "otel")]
+  resource.extend(
+    attributes
+      .iter()
+      .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+  );
+  opentelemetry_sdk::Resource::new(resource)
+}
+
+#[cfg(feature = "otel")]
+/// Skips the events of the OTLP exporter's own stack, which would be exported again while exporting.
+fn is_not_exporter_event(metadata: &tracing::Metadata) -> bool {
+  !["opentelemetry", "tonic", "h2", "hyper", "tower"]
+    .iter()
+    .any(|prefix| metadata.target().starts_with(prefix))
+}
+
 #[allow(dead_code)]
 fn log_filter(metadata: &tracing::Metadata) -> bool {
   metadata.module_path().is_none_or(|p| {
@@ -355,21 +1090,20 @@ fn init_logging(
   log_file_level: &MResult<tracing::Level>,
   log_rolling: tracing_appender::rolling::Rotation,
   log_rolling_max_files: &Option<u32>,
-  #[cfg(feature = "otel")] open_telemetry_endpoint: &Option<String>,
-) -> MResult<Option<TracingFileGuard>> {
+  #[cfg(feature = "otel")] values: &GenericValues,
+  #[cfg(feature = "otel")] shutdown_hooks: &ShutdownHooks,
+) -> MResult<(Option<TracingFileGuard>, LogLevelHandles)> {
   use tracing_appender::rolling;
   #[allow(unused_imports)]
-  use tracing_subscriber::filter::{LevelFilter, filter_fn};
+  use tracing_subscriber::filter::filter_fn;
   use tracing_subscriber::fmt::format::FmtSpan;
   use tracing_subscriber::prelude::*;
   use tracing_subscriber::{fmt, registry};
 
   #[cfg(feature = "otel")]
-  use crate::otel::api::{KeyValue, trace::TracerProvider};
-  #[cfg(feature = "otel")]
-  use crate::otel::exporter::WithExportConfig;
+  use crate::otel::api::trace::TracerProvider;
   #[cfg(feature = "otel")]
-  use crate::otel::sdk::{Resource, trace::RandomIdGenerator};
+  use crate::telemetry::{logger_provider, shutdown_exporter, tracer_provider};
 
   let format = fmt::format()
     .with_level(true)
@@ -380,30 +1114,31 @@ fn init_logging(
     .with_line_number(true)
     .compact();
 
-  let io_tracer = if let Ok(log_level) = log_level {
+  let (io_tracer, io_level) = if let Ok(log_level) = log_level {
+    let (level, level_handle) = reload::Layer::new(LevelFilter::from_level(*log_level));
     #[cfg(not(feature = "log-without-filtering"))]
     let io_tracer = fmt::layer()
       .event_format(format.clone())
       .with_writer(std::io::stdout)
       .with_span_events(FmtSpan::CLOSE)
-      .with_filter(LevelFilter::from_level(*log_level))
+      .with_filter(level)
       .with_filter(filter_fn(log_filter));
     #[cfg(feature = "log-without-filtering")]
     let io_tracer = fmt::layer()
       .event_format(format.clone())
       .with_writer(std::io::stdout)
       .with_span_events(FmtSpan::CLOSE)
-      .with_filter(LevelFilter::from_level(*log_level));
-    Some(io_tracer)
+      .with_filter(level);
+    (Some(io_tracer), Some(level_handle))
   } else {
-    None
+    (None, None)
   };
 
-  let (file_tracer, guard) = if let Ok(log_file_level) = log_file_level {
+  let (file_tracer, file_level, guard) = if let Ok(log_file_level) = log_file_level {
     let file_appender = rolling::RollingFileAppender::builder()
       .rotation(log_rolling)
       .filename_suffix(app_name)
-      .max_log_files(log_rolling_max_files.unwrap_or(5) as usize)
+      .max_log_files(log_rolling_max_files.unwrap_or(DEFAULT_LOG_ROLLING_MAX_FILES) as usize)
       .build("logs")
       .map_err(|_| {
         ErrorResponse::from("Failed to initialize logging to file!")
@@ -411,6 +1146,7 @@ fn init_logging(
           .build()
       })?;
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+    let (level, level_handle) = reload::Layer::new(LevelFilter::from_level(*log_file_level));
 
     #[cfg(not(feature = "log-without-filtering"))]
     let file_tracer = fmt::layer()
@@ -418,7 +1154,7 @@ fn init_logging(
       .with_writer(non_blocking)
       .with_ansi(false)
       .with_span_events(FmtSpan::CLOSE)
-      .with_filter(LevelFilter::from_level(*log_file_level))
+      .with_filter(level)
       .with_filter(filter_fn(log_filter));
     #[cfg(feature = "log-without-filtering")]
     let file_tracer = fmt::layer()
@@ -426,52 +1162,215 @@ fn init_logging(
       .with_writer(non_blocking)
       .with_ansi(false)
       .with_span_events(FmtSpan::CLOSE)
-      .with_filter(LevelFilter::from_level(*log_file_level));
+      .with_filter(level);
 
-    (Some(file_tracer), Some(guard))
+    (Some(file_tracer), Some(level_handle), Some(guard))
   } else {
-    (None, None)
+    (None, None, None)
   };
 
   #[cfg(feature = "otel")]
-  let otel_tracer = if let Some(open_telemetry_endpoint) = open_telemetry_endpoint
+  let (otel_tracer, otel_level) = if let Some(open_telemetry_endpoint) = &values.open_telemetry_endpoint
     && let Ok(log_level) = log_level
   {
-    let otel_span_exporter = opentelemetry_otlp::SpanExporter::builder()
-      .with_tonic()
-      .with_endpoint(open_telemetry_endpoint.as_str())
-      .build()
-      .map_err(|_| ErrorResponse::from("Failed to initialize OTEL telemetry!"))?;
-    let otel_provider = opentelemetry_sdk::trace::TracerProvider::builder()
-      .with_simple_exporter(otel_span_exporter)
-      .with_id_generator(RandomIdGenerator::default())
-      .with_max_events_per_span(32)
-      .with_max_attributes_per_span(64)
-      .with_resource(Resource::new(vec![KeyValue::new("service.name", app_name.to_owned())]))
-      .build()
-      .tracer(app_name.to_owned());
+    let otel_provider = tracer_provider(app_name, open_telemetry_endpoint, &values.open_telemetry)?;
+    let provider = otel_provider.clone();
+    shutdown_hooks.register(shutdown_exporter("traces", move || provider.shutdown()));
+    let otel_provider = otel_provider.tracer(app_name.to_owned());
+    let (level, level_handle) = reload::Layer::new(LevelFilter::from_level(*log_level));
 
     #[cfg(not(feature = "log-without-filtering"))]
     let opentelemetry = tracing_opentelemetry::layer()
       .with_tracer(otel_provider)
-      .with_filter(LevelFilter::from_level(*log_level))
+      .with_filter(level)
       .with_filter(filter_fn(log_filter));
     #[cfg(feature = "log-without-filtering")]
     let opentelemetry = tracing_opentelemetry::layer()
       .with_tracer(otel_provider)
-      .with_filter(LevelFilter::from_level(*log_level));
+      .with_filter(level);
 
-    Some(opentelemetry)
+    (Some(opentelemetry), Some(level_handle))
   } else {
-    None
+    (None, None)
   };
 
   #[cfg(feature = "otel")]
-  let collector = registry().with(io_tracer).with(file_tracer).with(otel_tracer);
+  let (otel_logs, otel_logs_level) = if values.open_telemetry_logs.is_some_and(|v| v)
+    && let Some(open_telemetry_endpoint) = &values.open_telemetry_endpoint
+    && let Ok(log_level) = log_level
+  {
+    let otel_provider = logger_provider(app_name, open_telemetry_endpoint, &values.open_telemetry)?;
+    let provider = otel_provider.clone();
+    shutdown_hooks.register(shutdown_exporter("logs", move || provider.shutdown()));
+    let (level, level_handle) = reload::Layer::new(LevelFilter::from_level(*log_level));
+
+    #[cfg(not(feature = "log-without-filtering"))]
+    let logs = opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge::new(&otel_provider)
+      .with_filter(level)
+      .with_filter(filter_fn(log_filter))
+      .with_filter(filter_fn(is_not_exporter_event));
+    #[cfg(feature = "log-without-filtering")]
+    let logs = opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge::new(&otel_provider)
+      .with_filter(level)
+      .with_filter(filter_fn(is_not_exporter_event));
+
+    (Some(logs), Some(level_handle))
+  } else {
+    (None, None)
+  };
+
+  #[cfg(feature = "otel")]
+  let collector = registry()
+    .with(io_tracer)
+    .with(file_tracer)
+    .with(otel_tracer)
+    .with(otel_logs);
   #[cfg(not(feature = "otel"))]
   let collector = registry().with(io_tracer).with(file_tracer);
 
+  let mut log_levels = LogLevelHandles::default();
+  if let Some(handle) = io_level {
+    log_levels.stdout.push(level_setter(handle));
+  }
+  #[cfg(feature = "otel")]
+  if let Some(handle) = otel_level {
+    log_levels.stdout.push(level_setter(handle));
+  }
+  #[cfg(feature = "otel")]
+  if let Some(handle) = otel_logs_level {
+    log_levels.stdout.push(level_setter(handle));
+  }
+  if let Some(handle) = file_level {
+    log_levels.file = Some(level_setter(handle));
+  }
+
   tracing::subscriber::set_global_default(collector)?;
 
-  Ok(guard)
+  Ok((guard, log_levels))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn variant_error(yaml: &str) -> String {
+    serde_yaml::from_str::<StartupVariant>(yaml).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn variant_without_its_field_is_rejected() {
+    assert!(variant_error("startup_type: unsafe_http").contains("missing field `server_host`"));
+    assert!(
+      variant_error("startup_type: https_only\nserver_host: 0.0.0.0\nssl_crt_path: certs/fullchain.pem")
+        .contains("missing field `ssl_key_path`")
+    );
+    #[cfg(feature = "acme")]
+    assert!(variant_error("startup_type: https_acme\nserver_host: 0.0.0.0").contains("missing field `acme_domain`"));
+    #[cfg(unix)]
+    assert!(variant_error("startup_type: unix_socket").contains("missing field `socket_path`"));
+  }
+
+  #[test]
+  fn listener_without_its_field_is_rejected_at_its_path() {
+    let yaml = "
+startup_type: http_localhost
+listeners:
+  - { startup_type: http_localhost, server_port: 9090 }
+  - { startup_type: https_only, server_host: 0.0.0.0, ssl_key_path: certs/privkey.pem }
+";
+    let Err(error) = serde_path_to_error::deserialize::<_, GenericValues>(serde_yaml::Deserializer::from_str(yaml))
+    else {
+      panic!("The listener without `ssl_crt_path` is parsed.");
+    };
+    assert_eq!(error.path().to_string(), "listeners[1]");
+    assert!(error.inner().to_string().contains("missing field `ssl_crt_path`"));
+  }
+
+  #[test]
+  fn variant_with_its_fields_is_parsed() {
+    let variant: StartupVariant =
+      serde_yaml::from_str("startup_type: unsafe_http\nserver_host: 0.0.0.0\nserver_port: 8080").unwrap();
+    assert_eq!(
+      variant,
+      StartupVariant::UnsafeHttp {
+        server_host: "0.0.0.0".into(),
+        server_port: 8080,
+      }
+    );
+    let variant: StartupVariant = serde_yaml::from_str("startup_type: http_localhost").unwrap();
+    assert_eq!(variant.server_port(), Some(default_server_port()));
+  }
+
+  fn header_names(settings: &SecurityHeaders) -> Vec<&'static str> {
+    settings.headers().into_iter().map(|(name, _)| name).collect()
+  }
+
+  #[test]
+  fn security_headers_presets_are_applied() {
+    let api = SecurityHeaders {
+      preset: Some(SecurityHeadersPreset::Api),
+      ..Default::default()
+    };
+    assert_eq!(
+      api.headers(),
+      vec![
+        ("x-content-type-options", "nosniff".to_string()),
+        (
+          "content-security-policy",
+          "default-src 'none'; frame-ancestors 'none'".to_string()
+        ),
+        ("x-frame-options", "DENY".to_string()),
+        ("referrer-policy", "no-referrer".to_string()),
+      ]
+    );
+    assert_eq!(api.hsts_policy(), Some(HstsPolicy::default()));
+
+    let browser = SecurityHeaders {
+      preset: Some(SecurityHeadersPreset::Browser),
+      ..Default::default()
+    };
+    assert_eq!(
+      header_names(&browser),
+      vec![
+        "x-content-type-options",
+        "content-security-policy",
+        "x-frame-options",
+        "referrer-policy",
+        "permissions-policy"
+      ]
+    );
+    assert_eq!(browser.headers()[2].1, "SAMEORIGIN");
+
+    let none = SecurityHeaders::default();
+    assert_eq!(header_names(&none), vec!["x-content-type-options"]);
+    assert_eq!(none.hsts_policy(), None);
+  }
+
+  #[test]
+  fn security_headers_override_the_preset() {
+    let settings = SecurityHeaders {
+      preset: Some(SecurityHeadersPreset::Browser),
+      hsts: Some(HstsPolicy {
+        max_age: 600,
+        include_subdomains: true,
+        preload: false,
+      }),
+      frame_options: Some("DENY".into()),
+      content_security_policy: Some("".into()),
+      permissions_policy: Some("".into()),
+      ..Default::default()
+    };
+    assert_eq!(
+      settings.headers(),
+      vec![
+        ("x-content-type-options", "nosniff".to_string()),
+        ("x-frame-options", "DENY".to_string()),
+        ("referrer-policy", "strict-origin-when-cross-origin".to_string()),
+      ]
+    );
+    assert_eq!(
+      settings.hsts_policy().map(|hsts| hsts.header_value()).as_deref(),
+      Some("max-age=600; includeSubDomains")
+    );
+  }
 }
@@ -0,0 +1,281 @@
+//! Health endpoints module.
+//!
+//! With `allow_health_endpoints: true` the root router serves:
+//!
+//! - `/healthz` (liveness): answers `200 OK` while the server is running;
+//! - `/readyz` (readiness): runs the registered `HealthCheck`s concurrently and answers `200 OK` if all of them pass,
+//!   or `503 Service Unavailable` if any fails or the server is shutting down.
+//!
+//! Both answer with JSON report:
+//!
+//! ```json
+//! {
+//!   "status": "failed",
+//!   "checks": { "database": { "status": "ok" }, "billing": { "status": "failed", "error": "timed out" } }
+//! }
+//! ```
+
+use salvo::http::StatusCode;
+use salvo::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Readiness check of a dependency, e.g. a database ping or a downstream reachability probe.
+///
+/// ```rust,ignore
+/// struct DatabaseCheck(PgPool);
+///
+/// impl HealthCheck for DatabaseCheck {
+///   fn name(&self) -> &str {
+///     "database"
+///   }
+///
+///   fn check(&self) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>> {
+///     Box::pin(async move { self.0.acquire().await.map(|_| ()).map_err(|e| e.to_string()) })
+///   }
+/// }
+///
+/// state.health_checks.register(DatabaseCheck(pool.clone()));
+/// ```
+pub trait HealthCheck: Send + Sync + 'static {
+  /// Name of the check in the report.
+  fn name(&self) -> &str;
+  /// Returns the reason if the dependency isn't ready.
+  fn check(&self) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>>;
+}
+
+/// Status of the server or of a check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+  /// Ready.
+  Ok,
+  /// A check has failed.
+  Failed,
+  /// The server is shutting down and doesn't accept new requests.
+  ShuttingDown,
+}
+
+/// Result of a check.
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckResult {
+  /// `ok` or `failed`.
+  pub status: HealthStatus,
+  /// The reason of the failure.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+}
+
+/// Health report.
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthReport {
+  /// Overall status.
+  pub status: HealthStatus,
+  /// Results of the checks by their names.
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  pub checks: BTreeMap<String, CheckResult>,
+}
+
+impl HealthReport {
+  fn status_code(&self) -> StatusCode {
+    match self.status {
+      HealthStatus::Ok => StatusCode::OK,
+      _ => StatusCode::SERVICE_UNAVAILABLE,
+    }
+  }
+}
+
+/// Registered readiness checks, available as `GenericServerState::health_checks`.
+#[derive(Clone, Default)]
+pub struct HealthChecks {
+  checks: Arc<RwLock<Vec<Arc<dyn HealthCheck>>>>,
+  shutting_down: Arc<AtomicBool>,
+}
+
+impl HealthChecks {
+  /// Registers the readiness check.
+  pub fn register(&self, check: impl HealthCheck) {
+    if let Ok(mut checks) = self.checks.write() {
+      checks.push(Arc::new(check));
+    }
+  }
+
+  /// Returns `true` after the shutdown signal is received.
+  pub fn is_shutting_down(&self) -> bool {
+    self.shutting_down.load(Ordering::Relaxed)
+  }
+
+  /// Makes the server not ready, so the load balancer stops sending new requests.
+  pub(crate) fn set_shutting_down(&self) {
+    self.shutting_down.store(true, Ordering::Relaxed);
+  }
+
+  /// Runs all the checks concurrently; a check which hasn't finished in `timeout` is failed.
+  pub async fn report(&self, timeout: Duration) -> HealthReport {
+    if self.is_shutting_down() {
+      return HealthReport {
+        status: HealthStatus::ShuttingDown,
+        checks: BTreeMap::new(),
+      };
+    }
+
+    let checks = self.checks.read().map(|checks| checks.clone()).unwrap_or_default();
+    let tasks = checks
+      .into_iter()
+      .map(|check| {
+        tokio::spawn(async move {
+          let result = tokio::time::timeout(timeout, check.check())
+            .await
+            .unwrap_or_else(|_| Err("timed out".into()));
+          (check.name().to_string(), result)
+        })
+      })
+      .collect::<Vec<_>>();
+
+    let mut report = HealthReport {
+      status: HealthStatus::Ok,
+      checks: BTreeMap::new(),
+    };
+    for task in tasks {
+      let (name, result) = task.await.unwrap_or_else(|e| ("unknown".into(), Err(e.to_string())));
+      if result.is_err() {
+        report.status = HealthStatus::Failed;
+      }
+      report.checks.insert(
+        name,
+        CheckResult {
+          status: if result.is_ok() {
+            HealthStatus::Ok
+          } else {
+            HealthStatus::Failed
+          },
+          error: result.err(),
+        },
+      );
+    }
+    report
+  }
+}
+
+struct Liveness;
+
+#[handler]
+impl Liveness {
+  async fn handle(&self, res: &mut Response) {
+    res.render(Json(HealthReport {
+      status: HealthStatus::Ok,
+      checks: BTreeMap::new(),
+    }));
+  }
+}
+
+struct Readiness {
+  checks: HealthChecks,
+  timeout: Duration,
+}
+
+#[handler]
+impl Readiness {
+  async fn handle(&self, res: &mut Response) {
+    let report = self.checks.report(self.timeout).await;
+    res.status_code(report.status_code());
+    res.render(Json(report));
+  }
+}
+
+/// Returns the router serving `/healthz` and `/readyz`; `get_root_router` mounts it if `allow_health_endpoints` is set.
+pub fn health_router(checks: HealthChecks, timeout: Duration) -> Router {
+  Router::new()
+    .push(Router::with_path("healthz").get(Liveness))
+    .push(Router::with_path("readyz").get(Readiness { checks, timeout }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  struct TestCheck {
+    name: &'static str,
+    delay: Duration,
+    result: Result<(), &'static str>,
+  }
+
+  impl HealthCheck for TestCheck {
+    fn name(&self) -> &str {
+      self.name
+    }
+
+    fn check(&self) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send + '_>> {
+      Box::pin(async move {
+        tokio::time::sleep(self.delay).await;
+        self.result.map_err(String::from)
+      })
+    }
+  }
+
+  fn checks(list: &[(&'static str, u64, Result<(), &'static str>)]) -> HealthChecks {
+    let checks = HealthChecks::default();
+    for (name, delay, result) in list {
+      checks.register(TestCheck {
+        name,
+        delay: Duration::from_millis(*delay),
+        result: *result,
+      });
+    }
+    checks
+  }
+
+  #[tokio::test]
+  async fn passed_checks_are_ready() {
+    let report = checks(&[("database", 0, Ok(())), ("cache", 10, Ok(()))])
+      .report(Duration::from_secs(1))
+      .await;
+    assert_eq!(report.status_code(), StatusCode::OK);
+    assert_eq!(
+      serde_json::to_value(&report).unwrap(),
+      json!({ "status": "ok", "checks": { "cache": { "status": "ok" }, "database": { "status": "ok" } } })
+    );
+  }
+
+  #[tokio::test]
+  async fn failed_and_timed_out_checks_are_reported() {
+    let report = checks(&[
+      ("database", 0, Ok(())),
+      ("billing", 0, Err("connection refused")),
+      ("search", 5000, Ok(())),
+    ])
+    .report(Duration::from_millis(50))
+    .await;
+    assert_eq!(report.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+      serde_json::to_value(&report).unwrap(),
+      json!({
+        "status": "failed",
+        "checks": {
+          "billing": { "status": "failed", "error": "connection refused" },
+          "database": { "status": "ok" },
+          "search": { "status": "failed", "error": "timed out" }
+        }
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn shutting_down_server_is_not_ready() {
+    let checks = checks(&[("database", 0, Ok(()))]);
+    checks.set_shutting_down();
+    assert!(checks.is_shutting_down());
+    let report = checks.report(Duration::from_secs(1)).await;
+    assert_eq!(report.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+      serde_json::to_value(&report).unwrap(),
+      json!({ "status": "shutting_down" })
+    );
+  }
+}
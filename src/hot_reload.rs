@@ -0,0 +1,323 @@
+//! Configuration hot reload module.
+//!
+//! Watches the configuration files, re-reads them on change and publishes the new configuration through
+//! `tokio::sync::watch` channel. Log levels, CORS domain and your own fields are applied live; the changes which need
+//! to rebind the listeners or to restart the subsystems are logged as warnings.
+
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use cc_utils::prelude::*;
+
+use crate::generic_setup::{GenericServerState, GenericSetup, GenericValues, log_level_filter};
+use crate::validation::parse_generic_config;
+
+/// Live configuration; inject it into `Depot` and read the current value with `borrow()`.
+pub type ConfigWatch<T> = watch::Receiver<T>;
+
+/// Editors often write the file in several steps, so the events are collected for a while before reloading.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Reads the configuration files; a file which can't be read is `None`.
+fn read_files(data: &GenericValues) -> Vec<(PathBuf, Option<Vec<u8>>)> {
+  data
+    .config_origin
+    .files
+    .iter()
+    .map(|file| (file.clone(), std::fs::read(file).ok()))
+    .collect()
+}
+
+/// Watches the directories instead of the files, because editors and Kubernetes replace files with renames.
+fn watch_dirs(
+  watcher: &mut RecommendedWatcher,
+  files: &[(PathBuf, Option<Vec<u8>>)],
+  watched: &mut HashSet<PathBuf>,
+) -> MResult<()> {
+  for dir in files.iter().filter_map(|(file, _)| file.parent()) {
+    if watched.contains(dir) {
+      continue;
+    }
+    watcher
+      .watch(dir, RecursiveMode::NonRecursive)
+      .map_err(|e| ErrorResponse::from(e.to_string()).with_500_pub().build())?;
+    watched.insert(dir.to_path_buf());
+  }
+  Ok(())
+}
+
+/// Reads the same configuration files again.
+///
+/// Any event in the watched directories may change the files, so the contents are compared instead of the event
+/// paths: Kubernetes, for example, updates a mounted ConfigMap by swapping the `..data` symlink, which the files are
+/// linked through, so no event names the files themselves.
+fn read_again(files: &[(PathBuf, Option<Vec<u8>>)]) -> Vec<(PathBuf, Option<Vec<u8>>)> {
+  files
+    .iter()
+    .map(|(file, _)| (file.clone(), std::fs::read(file).ok()))
+    .collect()
+}
+
+/// Returns the names of changed fields which can't be applied without restart.
+fn restart_required(old: &GenericValues, new: &GenericValues) -> Vec<&'static str> {
+  let mut changed = vec![];
+  if old.startup_variant != new.startup_variant {
+    changed.push("startup_type");
+  }
+  if old.listeners != new.listeners {
+    changed.push("listeners");
+  }
+  if old.admin != new.admin {
+    changed.push("admin");
+  }
+  if old.tls != new.tls {
+    changed.push("tls");
+  }
+  #[cfg(feature = "force-https")]
+  if old.redirect_http_port != new.redirect_http_port {
+    changed.push("redirect_http_port");
+  }
+  if old.security_headers != new.security_headers {
+    changed.push("security_headers");
+  }
+  if old.server_port_achiever != new.server_port_achiever {
+    changed.push("server_port_achiever");
+  }
+  if old.auto_migrate_bin != new.auto_migrate_bin {
+    changed.push("auto_migrate_bin");
+  }
+  #[cfg(feature = "oapi")]
+  if old.allow_oapi_access != new.allow_oapi_access
+    || old.oapi_frontend_type != new.oapi_frontend_type
+    || old.oapi_name != new.oapi_name
+    || old.oapi_ver != new.oapi_ver
+    || old.oapi_api_addr != new.oapi_api_addr
+  {
+    changed.push("oapi");
+  }
+  if old.log_rolling != new.log_rolling || old.log_rolling_max_files != new.log_rolling_max_files {
+    changed.push("log_rolling");
+  }
+  #[cfg(feature = "otel")]
+  if old.open_telemetry_endpoint != new.open_telemetry_endpoint {
+    changed.push("open_telemetry_endpoint");
+  }
+  #[cfg(feature = "otel")]
+  if old.open_telemetry != new.open_telemetry {
+    changed.push("open_telemetry");
+  }
+  #[cfg(all(feature = "otel", feature = "metrics"))]
+  if old.open_telemetry_metrics != new.open_telemetry_metrics {
+    changed.push("open_telemetry_metrics");
+  }
+  #[cfg(feature = "otel")]
+  if old.open_telemetry_logs != new.open_telemetry_logs {
+    changed.push("open_telemetry_logs");
+  }
+  if old.allow_health_endpoints != new.allow_health_endpoints || old.health_check_timeout != new.health_check_timeout {
+    changed.push("health");
+  }
+  #[cfg(feature = "metrics")]
+  if old.allow_metrics != new.allow_metrics || old.metrics_path != new.metrics_path {
+    changed.push("metrics");
+  }
+  if old.pre_stop_delay != new.pre_stop_delay {
+    changed.push("pre_stop_delay");
+  }
+  if old.shutdown_grace_period != new.shutdown_grace_period {
+    changed.push("shutdown_grace_period");
+  }
+  if old.hot_reload != new.hot_reload {
+    changed.push("hot_reload");
+  }
+  changed
+}
+
+/// Applies the reloadable generic values to the running server.
+fn apply_generic_values(state: &GenericServerState, old: &GenericValues, new: &GenericValues) {
+  if old.log_level != new.log_level
+    && state
+      .log_levels
+      .set_log_level(log_level_filter(&new.log_level))
+      .is_err()
+  {
+    tracing::warn!("`log_level` has changed, but can't be applied; restart the server to apply it.");
+  }
+  if old.log_file_level != new.log_file_level
+    && state
+      .log_levels
+      .set_log_file_level(log_level_filter(&new.log_file_level))
+      .is_err()
+  {
+    tracing::warn!("`log_file_level` has changed, but can't be applied; restart the server to apply it.");
+  }
+  for field in restart_required(old, new) {
+    tracing::warn!("`{}` has changed; restart the server to apply it.", field);
+  }
+  state.live_values.send_replace(new.clone());
+}
+
+/// Starts watching the configuration files and returns the live configuration.
+///
+/// On every change the configuration is loaded and checked again the same way as `load_generic_config` does; if it
+/// has problems, they are logged and the previous configuration is kept. `get_root_router_autoinject` calls it for
+/// you when `hot_reload: true` is set and injects the result into `Depot`:
+///
+/// ```rust,ignore
+/// let setup = depot.obtain::<ConfigWatch<Setup>>()?.borrow().clone();
+/// ```
+pub fn watch_generic_config<T>(setup: &T, state: &GenericServerState) -> MResult<ConfigWatch<T>>
+where
+  T: DeserializeOwned + GenericSetup + Clone + Send + Sync + 'static,
+{
+  let data = setup.generic_values();
+  let source = data.config_origin.source.clone();
+  let mut files = read_files(data);
+
+  let (events_tx, mut events_rx) = tokio::sync::mpsc::channel(16);
+  let mut watcher = RecommendedWatcher::new(
+    move |res| {
+      let _ = events_tx.blocking_send(res);
+    },
+    Config::default(),
+  )
+  .map_err(|e| ErrorResponse::from(e.to_string()).with_500_pub().build())?;
+  let mut watched = HashSet::new();
+  watch_dirs(&mut watcher, &files, &mut watched)?;
+
+  let (tx, rx) = watch::channel(setup.clone());
+  let state = state.clone();
+
+  tokio::spawn(async move {
+    while let Some(res) = events_rx.recv().await {
+      match res {
+        Ok(event) if !event.kind.is_access() => {}
+        Ok(_) => continue,
+        Err(e) => {
+          tracing::error!("Configuration watch error: {:?}", e);
+          continue;
+        }
+      }
+      tokio::time::sleep(DEBOUNCE).await;
+      while events_rx.try_recv().is_ok() {}
+      let current = read_again(&files);
+      if current == files {
+        continue;
+      }
+      files = current;
+
+      let (config, report) = parse_generic_config::<T>(&source);
+      let Some(mut config) = config.filter(|_| report.is_ok()) else {
+        tracing::error!("The configuration is not reloaded. {}", report);
+        continue;
+      };
+
+      let old = tx.borrow().generic_values().clone();
+      let new = config.generic_values_mut();
+      if new.server_port_achiever.is_some()
+        && new.server_port_achiever == old.server_port_achiever
+        && let Some(port) = old.startup_variant.server_port()
+      {
+        new.startup_variant.set_server_port(port);
+      }
+
+      files = read_files(new);
+      if watch_dirs(&mut watcher, &files, &mut watched).is_err() {
+        tracing::warn!("Failed to watch the directories of newly included configuration files.");
+      }
+
+      apply_generic_values(&state, &old, new);
+      tx.send_replace(config);
+      tracing::info!("The configuration is reloaded.");
+    }
+  });
+
+  Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Arc;
+
+  use crate::generic_setup::StartupVariant;
+
+  #[test]
+  fn reloadable_fields_are_not_reported() {
+    let old = GenericValues::default();
+    let mut new = old.clone();
+    new.log_level = Some("info".into());
+    new.log_file_level = Some("warn".into());
+    #[cfg(feature = "cors")]
+    {
+      new.allow_cors_domain = Some("https://example.com".into());
+    }
+    assert!(restart_required(&old, &new).is_empty());
+
+    new.listeners = vec![StartupVariant::default()];
+    new.admin = Some(StartupVariant::default());
+    new.pre_stop_delay = Some(5);
+    new.hot_reload = Some(true);
+    assert_eq!(
+      restart_required(&old, &new),
+      vec!["listeners", "admin", "pre_stop_delay", "hot_reload"]
+    );
+  }
+
+  #[test]
+  fn new_values_are_published_even_if_log_levels_are_not_applied() {
+    let old = GenericValues::default();
+    let state = GenericServerState {
+      startup_variant: old.startup_variant.clone(),
+      listeners: vec![],
+      _file_log_guard: None,
+      log_levels: Default::default(),
+      live_values: Arc::new(watch::Sender::new(old.clone())),
+      health_checks: Default::default(),
+      host_routers: Default::default(),
+      shutdown_hooks: Default::default(),
+    };
+    let live = state.live_values.subscribe();
+    let mut new = old.clone();
+    new.log_level = Some("error".into());
+    new.shutdown_grace_period = Some(10);
+
+    apply_generic_values(&state, &old, &new);
+    assert_eq!(live.borrow().log_level.as_deref(), Some("error"));
+    assert_eq!(live.borrow().shutdown_grace_period, Some(10));
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn configmap_update_is_noticed() {
+    use std::os::unix::fs::symlink;
+
+    // Lays out the directory the way the kubelet mounts a ConfigMap: `app.yaml -> ..data/app.yaml`,
+    // `..data -> ..<timestamp>`; the update creates a new timestamped directory and swaps `..data` with a rename.
+    let dir = std::env::temp_dir().join(format!("cc-hot-reload-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("..2026_01")).unwrap();
+    std::fs::write(dir.join("..2026_01/app.yaml"), "log_level: debug\n").unwrap();
+    symlink("..2026_01", dir.join("..data")).unwrap();
+    symlink("..data/app.yaml", dir.join("app.yaml")).unwrap();
+
+    let mut data = GenericValues::default();
+    data.config_origin.files = vec![dir.join("app.yaml")];
+    let files = read_files(&data);
+    assert_eq!(read_again(&files), files);
+
+    std::fs::create_dir_all(dir.join("..2026_02")).unwrap();
+    std::fs::write(dir.join("..2026_02/app.yaml"), "log_level: info\n").unwrap();
+    symlink("..2026_02", dir.join("..data_tmp")).unwrap();
+    std::fs::rename(dir.join("..data_tmp"), dir.join("..data")).unwrap();
+
+    let current = read_again(&files);
+    assert_ne!(current, files);
+    assert_eq!(current[0].0, dir.join("app.yaml"));
+    assert_eq!(current[0].1.as_deref(), Some(b"log_level: info\n".as_slice()));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
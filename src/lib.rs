@@ -87,9 +87,29 @@
 #![feature(let_chains, stmt_expr_attributes)]
 #![deny(warnings, clippy::todo, clippy::unimplemented)]
 
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod client_cert;
+pub mod config;
 pub mod generic_setup;
+pub mod health;
+pub mod hot_reload;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod prelude;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod secrets;
+pub mod shutdown;
 pub mod startup;
+#[cfg(unix)]
+pub mod systemd;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod tls;
+#[cfg(unix)]
+pub mod unix_socket;
+pub mod validation;
 
 pub use salvo;
 
@@ -106,6 +126,9 @@ pub mod otel {
   pub use tracing_opentelemetry as tracing_otel;
 }
 
+#[cfg(feature = "metrics")]
+pub use prometheus;
+
 #[cfg(feature = "reqwest-msgpack")]
 pub use reqwest;
 
@@ -115,5 +138,8 @@ pub use cc_auth;
 #[cfg(feature = "cc-utils")]
 pub use cc_utils;
 
+#[cfg(feature = "schema")]
+pub use schemars;
+
 #[cfg(feature = "test")]
 pub mod test_exts;
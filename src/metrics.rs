@@ -0,0 +1,307 @@
+//! Metrics module.
+//!
+//! With `allow_metrics: true` the server records the HTTP metrics of the service's requests, labelled by the matched
+//! route's template (e.g. `/users/{id}`, or `unmatched`), the method and the status code:
+//!
+//! - `http_server_requests_total`: handled requests;
+//! - `http_server_request_duration_seconds`: handling time histogram;
+//! - `http_server_active_requests`: requests being handled;
+//!
+//! with the tokio runtime (`tokio_workers`, `tokio_alive_tasks`, `tokio_global_queue_depth`) and, on Linux, the
+//! process ones (`process_cpu_seconds_total`, `process_resident_memory_bytes`, `process_open_fds`, etc.), and serves
+//! them in Prometheus text format on `metrics_path`.
+//!
+//! The metrics are recorded with OpenTelemetry meter provider which is installed as the global one, so your own
+//! instruments created with `meter` are served along with them; plain Prometheus collectors can be registered in
+//! `metrics_registry`. With `otel` feature and `open_telemetry_metrics: true` the provider also exports the
+//! instruments (not the process metrics, which are Prometheus collectors) to `open_telemetry_endpoint` every minute.
+
+use opentelemetry::metrics::{Counter, Histogram, Meter, MeterProvider, ObservableGauge, UpDownCounter};
+use opentelemetry::{KeyValue, global};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use prometheus::{Encoder, Registry, TextEncoder};
+use salvo::http::header::CONTENT_TYPE;
+use salvo::http::{HeaderValue, StatusCode};
+use salvo::prelude::*;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use cc_utils::prelude::*;
+
+use crate::generic_setup::{GenericValues, otel_resource};
+
+/// Upper bounds of the request duration histogram's buckets, in seconds.
+const DURATION_BUCKETS: [f64; 14] = [
+  0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5, 10.0,
+];
+
+struct Metrics {
+  registry: Registry,
+  provider: SdkMeterProvider,
+  requests: Counter<u64>,
+  duration: Histogram<f64>,
+  active_requests: UpDownCounter<i64>,
+  _runtime: Vec<ObservableGauge<u64>>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+fn error(message: String) -> ErrorResponse {
+  ErrorResponse::from(message).with_500_pub().build()
+}
+
+/// Installs the meter provider exporting to Prometheus registry if `allow_metrics` is set and to OTLP endpoint if
+/// `open_telemetry_metrics` is, and creates the server's instruments.
+///
+/// Called by `load_generic_state` if the metrics are enabled; the following calls do nothing.
+pub(crate) fn init_metrics(values: &GenericValues) -> MResult<()> {
+  if METRICS.get().is_some() {
+    return Ok(());
+  }
+
+  let registry = Registry::new();
+  let mut provider = SdkMeterProvider::builder().with_resource(otel_resource(
+    &values.app_name,
+    #[cfg(feature = "otel")]
+    &values.open_telemetry.resource_attributes,
+  ));
+  if values.allow_metrics.is_some_and(|v| v) {
+    #[cfg(target_os = "linux")]
+    registry
+      .register(Box::new(prometheus::process_collector::ProcessCollector::for_self()))
+      .map_err(|e| error(format!("Failed to register the process metrics: {}", e)))?;
+
+    let exporter = opentelemetry_prometheus::exporter()
+      .with_registry(registry.clone())
+      .build()
+      .map_err(|e| error(format!("Failed to build Prometheus exporter: {}", e)))?;
+    provider = provider.with_reader(exporter);
+  }
+  #[cfg(feature = "otel")]
+  if values.open_telemetry_metrics.is_some_and(|v| v)
+    && let Some(endpoint) = values.open_telemetry_endpoint.as_deref()
+  {
+    let exporter = crate::telemetry::metric_exporter(endpoint, &values.open_telemetry)?;
+    provider = provider.with_reader(
+      opentelemetry_sdk::metrics::PeriodicReader::builder(exporter, opentelemetry_sdk::runtime::Tokio).build(),
+    );
+  }
+  let provider = provider.build();
+  global::set_meter_provider(provider.clone());
+
+  let meter = provider.meter("cc-server-kit");
+  let metrics = Metrics {
+    registry,
+    requests: meter
+      .u64_counter("http.server.requests")
+      .with_description("Number of handled HTTP requests.")
+      .build(),
+    duration: meter
+      .f64_histogram("http.server.request.duration")
+      .with_unit("s")
+      .with_description("Duration of HTTP requests' handling.")
+      .with_boundaries(DURATION_BUCKETS.to_vec())
+      .build(),
+    active_requests: meter
+      .i64_up_down_counter("http.server.active_requests")
+      .with_description("Number of HTTP requests being handled.")
+      .build(),
+    _runtime: runtime_gauges(&meter),
+    provider,
+  };
+  let _ = METRICS.set(metrics);
+  Ok(())
+}
+
+/// Creates the gauges observing the tokio runtime the metrics are rendered on.
+fn runtime_gauges(meter: &Meter) -> Vec<ObservableGauge<u64>> {
+  let gauge = |name: &'static str, description: &'static str, value: fn(&tokio::runtime::RuntimeMetrics) -> usize| {
+    meter
+      .u64_observable_gauge(name)
+      .with_description(description)
+      .with_callback(move |observer| {
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+          observer.observe(value(&handle.metrics()) as u64, &[]);
+        }
+      })
+      .build()
+  };
+  vec![
+    gauge("tokio.workers", "Number of the runtime's worker threads.", |m| {
+      m.num_workers()
+    }),
+    gauge("tokio.alive_tasks", "Number of alive tasks in the runtime.", |m| {
+      m.num_alive_tasks()
+    }),
+    gauge(
+      "tokio.global_queue_depth",
+      "Number of tasks in the runtime's global queue.",
+      |m| m.global_queue_depth(),
+    ),
+  ]
+}
+
+/// Exports the pending metrics and stops the exporters; registered as a shutdown hook by `load_generic_state`.
+pub(crate) async fn shutdown_metrics() {
+  let Some(metrics) = METRICS.get() else {
+    return;
+  };
+  let provider = metrics.provider.clone();
+  match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => tracing::warn!("Failed to shut the metrics exporters down: {}", e),
+    Err(e) => tracing::warn!("Failed to shut the metrics exporters down: {}", e),
+  }
+}
+
+/// Returns the meter to create your own instruments with; they're served along with the server's metrics.
+///
+/// ```rust,ignore
+/// let orders = cc_server_kit::metrics::meter("shop").u64_counter("shop.orders").build();
+/// orders.add(1, &[]);
+/// ```
+pub fn meter(name: &'static str) -> Meter {
+  global::meter(name)
+}
+
+/// Returns Prometheus registry the metrics are served from, to register your own collectors; `None` if the metrics
+/// aren't enabled.
+pub fn metrics_registry() -> Option<&'static Registry> {
+  METRICS.get().map(|metrics| &metrics.registry)
+}
+
+/// Renders all the metrics in Prometheus text format.
+pub fn render_metrics() -> MResult<String> {
+  let metrics = METRICS
+    .get()
+    .ok_or_else(|| error("Metrics aren't enabled; set `allow_metrics: true`.".into()))?;
+  let mut buffer = vec![];
+  TextEncoder::new()
+    .encode(&metrics.registry.gather(), &mut buffer)
+    .map_err(|e| error(format!("Failed to encode the metrics: {}", e)))?;
+  String::from_utf8(buffer).map_err(|e| error(e.to_string()))
+}
+
+/// Returns the template of the route the request has matched, e.g. `/users/{id}`, or `unmatched` if none.
+///
+/// The template is salvo's matched path, where the path parameters are written by their names.
+fn route_pattern(req: &Request, matched: bool) -> String {
+  if matched {
+    format!("/{}", req.matched_path())
+  } else {
+    "unmatched".to_string()
+  }
+}
+
+/// Hoop recording the HTTP metrics; `start` installs it as the service's first hoop if the metrics are enabled, so the
+/// unmatched requests are recorded too.
+pub struct HttpMetricsHoop;
+
+#[handler]
+impl HttpMetricsHoop {
+  async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let Some(metrics) = METRICS.get() else {
+      ctrl.call_next(req, depot, res).await;
+      return;
+    };
+
+    let method = KeyValue::new("http.request.method", req.method().to_string());
+    metrics.active_requests.add(1, std::slice::from_ref(&method));
+    // The service presets the status code of the requests no route has matched before calling its hoops.
+    let matched = res.status_code.is_none();
+    let started = Instant::now();
+    ctrl.call_next(req, depot, res).await;
+    metrics.active_requests.add(-1, std::slice::from_ref(&method));
+
+    let attributes = [
+      method,
+      KeyValue::new("http.route", route_pattern(req, matched)),
+      KeyValue::new(
+        "http.response.status_code",
+        res.status_code.unwrap_or(StatusCode::OK).as_u16() as i64,
+      ),
+    ];
+    metrics.requests.add(1, &attributes);
+    metrics.duration.record(started.elapsed().as_secs_f64(), &attributes);
+  }
+}
+
+struct MetricsEndpoint;
+
+#[handler]
+impl MetricsEndpoint {
+  async fn handle(&self, res: &mut Response) {
+    match render_metrics() {
+      Ok(metrics) => {
+        res
+          .headers_mut()
+          .insert(CONTENT_TYPE, HeaderValue::from_static(prometheus::TEXT_FORMAT));
+        if let Err(e) = res.write_body(metrics) {
+          tracing::error!("Failed to write the metrics: {}", e);
+        }
+      }
+      Err(e) => {
+        tracing::error!("Failed to render the metrics: {:?}", e);
+        res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+      }
+    }
+  }
+}
+
+/// Returns the router serving the metrics on `path`; `get_root_router` mounts it if `allow_metrics` is set.
+pub fn metrics_router(path: &str) -> Router {
+  Router::with_path(path.trim_start_matches('/')).get(MetricsEndpoint)
+}
+
+#[cfg(all(test, feature = "test"))]
+mod tests {
+  use super::*;
+
+  #[handler]
+  async fn hello() -> &'static str {
+    "hello"
+  }
+
+  /// Returns whether the requests' counter has a sample with all the labels.
+  fn counted(metrics: &str, labels: &[&str]) -> bool {
+    metrics
+      .lines()
+      .any(|line| line.starts_with("http_server_requests_total{") && labels.iter().all(|label| line.contains(label)))
+  }
+
+  #[tokio::test]
+  async fn requests_are_labelled_by_route_template_and_unmatched_ones_are_recorded() {
+    use salvo::test::TestClient;
+
+    let values = GenericValues {
+      app_name: "metrics-test".into(),
+      allow_metrics: Some(true),
+      ..Default::default()
+    };
+    init_metrics(&values).unwrap();
+    let router = Router::new()
+      .push(Router::with_path("users/{id}").get(hello))
+      .push(Router::with_path("health").get(hello));
+    let service = Service::new(router).hoop(HttpMetricsHoop);
+
+    TestClient::get("http://127.0.0.1/users/42").send(&service).await;
+    TestClient::get("http://127.0.0.1/users/43").send(&service).await;
+    TestClient::get("http://127.0.0.1/missing").send(&service).await;
+    TestClient::post("http://127.0.0.1/health").send(&service).await;
+
+    let metrics = render_metrics().unwrap();
+    assert!(counted(
+      &metrics,
+      &["http_route=\"/users/{id}\"", "http_response_status_code=\"200\""]
+    ));
+    assert!(!metrics.contains("/users/42"));
+    assert!(counted(
+      &metrics,
+      &["http_route=\"unmatched\"", "http_response_status_code=\"404\""]
+    ));
+    assert!(counted(
+      &metrics,
+      &["http_route=\"unmatched\"", "http_response_status_code=\"405\""]
+    ));
+  }
+}
@@ -6,8 +6,15 @@ pub use cc_utils::{
   prelude::{Consider, ErrorResponse, Json, MResult, MsgPack, MsgPackParser, OK, json, msgpack, ok},
 };
 
-pub use crate::generic_setup::{GenericSetup, GenericValues, load_generic_config, load_generic_state};
-pub use crate::startup::{get_root_router, start};
+pub use crate::client_cert::ClientCert;
+pub use crate::config::ConfigSource;
+pub use crate::generic_setup::{
+  GenericSetup, GenericValues, load_generic_config, load_generic_config_with_profile, load_generic_state,
+};
+pub use crate::health::HealthCheck;
+pub use crate::hot_reload::{ConfigWatch, watch_generic_config};
+pub use crate::startup::{get_root_router, host_router, start};
+pub use crate::validation::{ConfigReport, check_generic_config};
 pub use salvo;
 pub use tracing;
 pub use tracing::instrument;
@@ -18,5 +25,13 @@ pub use salvo::{Depot, Request, Router};
 #[cfg(feature = "oapi")]
 pub use salvo::oapi::endpoint;
 
+#[cfg(feature = "cli")]
+pub use crate::cli::run_cli;
+
+#[cfg(feature = "schema")]
+pub use crate::schema::generic_config_schema;
+#[cfg(feature = "schema")]
+pub use schemars::JsonSchema;
+
 #[cfg(feature = "test")]
 pub use crate::test_exts::*;
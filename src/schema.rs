@@ -0,0 +1,50 @@
+//! JSON Schema module.
+//!
+//! Generates the JSON Schema of the server configuration, so editors can autocomplete and validate your YAML file.
+//! The schema contains the fields enabled by the crate's features and the doc comments as descriptions.
+
+use schemars::JsonSchema;
+use schemars::schema::{InstanceType, Schema, SchemaObject, SingleOrVec};
+
+use crate::config::INCLUDE_KEY;
+use crate::generic_setup::GenericSetup;
+
+/// Generates the JSON Schema for your `GenericSetup` type with `GenericValues` flattened in it.
+///
+/// Derive `JsonSchema` for your type (`cc_server_kit::schemars` is re-exported) and save the result next to
+/// the configuration:
+///
+/// ```rust,ignore
+/// let schema = generic_config_schema::<Setup>();
+/// std::fs::write("server-example.schema.json", serde_json::to_string_pretty(&schema).unwrap()).unwrap();
+/// ```
+///
+/// Then point your editor to it, e.g. with `# yaml-language-server: $schema=server-example.schema.json` line.
+pub fn generic_config_schema<T: JsonSchema + GenericSetup>() -> serde_json::Value {
+  let mut schema = schemars::schema_for!(T);
+  schema.schema.metadata().description = Some("Configuration of the server built with CC Server Kit.".into());
+
+  let path = SchemaObject {
+    instance_type: Some(SingleOrVec::Single(Box::new(InstanceType::String))),
+    ..Default::default()
+  };
+  let include = SchemaObject {
+    instance_type: Some(SingleOrVec::Vec(vec![InstanceType::String, InstanceType::Array])),
+    array: Some(Box::new(schemars::schema::ArrayValidation {
+      items: Some(SingleOrVec::Single(Box::new(Schema::Object(path)))),
+      ..Default::default()
+    })),
+    metadata: Some(Box::new(schemars::schema::Metadata {
+      description: Some("Configuration fragment or fragments to include, relative to this file.".into()),
+      ..Default::default()
+    })),
+    ..Default::default()
+  };
+  schema
+    .schema
+    .object()
+    .properties
+    .insert(INCLUDE_KEY.into(), Schema::Object(include));
+
+  serde_json::to_value(schema).unwrap_or_default()
+}
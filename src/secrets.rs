@@ -0,0 +1,309 @@
+//! Secret references module.
+//!
+//! Any string value in the configuration can refer to a secret instead of containing it:
+//!
+//! - `${env:DB_PASSWORD}` is replaced with the value of the environment variable;
+//! - `${file:/run/secrets/db}` is replaced with the contents of the file (without trailing line break).
+//!
+//! The references can be a part of a longer string, e.g. `postgres://app:${env:DB_PASSWORD}@db/app`; write `$${`
+//! to get literal `${`. The references are resolved after all the files and environment overrides are merged, and the
+//! resolved values are remembered in `Secrets` to be redacted from everything the kit prints or logs.
+
+use serde_yaml::Value;
+use std::env::VarError;
+use std::fmt;
+
+use crate::validation::{ConfigIssue, ConfigReport};
+
+/// Replacement for the redacted values.
+pub const REDACTED: &str = "***";
+
+/// Resolved values shorter than this are not redacted from the text: replacing every occurrence of a couple of
+/// characters would garble the text while hiding next to nothing.
+pub const MIN_REDACTED_LEN: usize = 4;
+
+/// Secrets resolved while loading the configuration.
+#[derive(Clone, Default)]
+pub struct Secrets {
+  paths: Vec<String>,
+  /// Whole resolved values at `paths`.
+  values: Vec<String>,
+}
+
+impl Secrets {
+  /// Returns the paths to the values which contain secrets, e.g. `database.url`.
+  pub fn paths(&self) -> &[String] {
+    &self.paths
+  }
+
+  /// Returns `true` if no secrets were resolved.
+  pub fn is_empty(&self) -> bool {
+    self.paths.is_empty()
+  }
+
+  /// Replaces the values which contain secrets in the text with `***`.
+  ///
+  /// Only the whole resolved values of at least `MIN_REDACTED_LEN` characters are replaced, e.g. the whole
+  /// `postgres://app:p@ss@db/app` rather than `p@ss` wherever it occurs.
+  pub fn redact(&self, text: &str) -> String {
+    let mut values = self
+      .values
+      .iter()
+      .filter(|value| value.chars().count() >= MIN_REDACTED_LEN)
+      .collect::<Vec<_>>();
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    values
+      .into_iter()
+      .fold(text.to_string(), |text, value| text.replace(value.as_str(), REDACTED))
+  }
+
+  /// Replaces the values which contain secrets in the configuration document with `***`.
+  pub fn redact_document(&self, document: &mut Value) {
+    for path in &self.paths {
+      if let Some(value) = get_path_mut(document, path) {
+        *value = Value::String(REDACTED.to_string());
+      }
+    }
+  }
+}
+
+impl fmt::Debug for Secrets {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Secrets")
+      .field("paths", &self.paths)
+      .finish_non_exhaustive()
+  }
+}
+
+fn get_path_mut<'a>(document: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+  let mut value = document;
+  for part in path.split('.') {
+    let (key, indices) = match part.find('[') {
+      Some(pos) => (&part[..pos], &part[pos..]),
+      None => (part, ""),
+    };
+    if !key.is_empty() {
+      value = value.get_mut(key)?;
+    }
+    for index in indices.split(['[', ']']).filter(|index| !index.is_empty()) {
+      value = value.get_mut(index.parse::<usize>().ok()?)?;
+    }
+  }
+  Some(value)
+}
+
+/// Looks up the environment variable, like `std::env::var`.
+trait EnvLookup: Fn(&str) -> Result<String, VarError> {}
+
+impl<F: Fn(&str) -> Result<String, VarError>> EnvLookup for F {}
+
+fn resolve_reference(reference: &str, var: &impl EnvLookup) -> Result<String, String> {
+  if let Some(name) = reference.strip_prefix("env:") {
+    var(name).map_err(|e| format!("Failed to read the environment variable `{}`: {}", name, e))
+  } else if let Some(path) = reference.strip_prefix("file:") {
+    std::fs::read_to_string(path)
+      .map(|contents| contents.trim_end_matches(['\n', '\r']).to_string())
+      .map_err(|e| format!("Failed to read the secret file `{}`: {}", path, e))
+  } else {
+    Err(format!(
+      "Unknown reference `${{{}}}`. Use `${{env:NAME}}` or `${{file:/path}}`, or write `$${{` to get literal `${{`.",
+      reference
+    ))
+  }
+}
+
+/// Resolves the references in the string, counting them in `references`; returns `None` if there is nothing to
+/// resolve.
+fn resolve_string(raw: &str, var: &impl EnvLookup, references: &mut usize) -> Result<Option<String>, String> {
+  if !raw.contains("${") {
+    return Ok(None);
+  }
+
+  let mut resolved = String::with_capacity(raw.len());
+  let mut rest = raw;
+  while let Some(pos) = rest.find("${") {
+    if rest[..pos].ends_with('$') {
+      resolved.push_str(&rest[..pos - 1]);
+      resolved.push_str("${");
+      rest = &rest[pos + 2..];
+      continue;
+    }
+    resolved.push_str(&rest[..pos]);
+    let Some(end) = rest[pos..].find('}') else {
+      return Err("The reference is not closed with `}`.".into());
+    };
+    resolved.push_str(&resolve_reference(&rest[pos + 2..pos + end], var)?);
+    *references += 1;
+    rest = &rest[pos + end + 1..];
+  }
+  resolved.push_str(rest);
+  Ok(Some(resolved))
+}
+
+fn resolve_value(
+  value: &mut Value,
+  path: &str,
+  var: &impl EnvLookup,
+  secrets: &mut Secrets,
+  report: &mut ConfigReport,
+) {
+  match value {
+    Value::Mapping(mapping) => {
+      for (key, value) in mapping.iter_mut() {
+        let key = match key {
+          Value::String(key) => key.clone(),
+          key => serde_yaml::to_string(key).unwrap_or_default().trim_end().to_string(),
+        };
+        let path = if path.is_empty() {
+          key
+        } else {
+          format!("{}.{}", path, key)
+        };
+        resolve_value(value, &path, var, secrets, report);
+      }
+    }
+    Value::Sequence(sequence) => {
+      for (index, value) in sequence.iter_mut().enumerate() {
+        resolve_value(value, &format!("{}[{}]", path, index), var, secrets, report);
+      }
+    }
+    Value::String(raw) => {
+      let mut references = 0;
+      match resolve_string(raw, var, &mut references) {
+        Ok(Some(resolved)) => {
+          if references > 0 {
+            secrets.paths.push(path.to_string());
+            secrets.values.push(resolved.clone());
+          }
+          *raw = resolved;
+        }
+        Ok(None) => {}
+        Err(message) => report.push(ConfigIssue::new(message).at(path)),
+      }
+    }
+    Value::Tagged(tagged) => resolve_value(&mut tagged.value, path, var, secrets, report),
+    _ => {}
+  }
+}
+
+/// Resolves all the secret references in the configuration document.
+pub(crate) fn resolve_secrets(document: &mut Value, report: &mut ConfigReport) -> Secrets {
+  let mut secrets = Secrets::default();
+  resolve_value(document, "", &|name: &str| std::env::var(name), &mut secrets, report);
+  secrets
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashMap;
+
+  fn env(vars: &[(&str, &str)]) -> impl EnvLookup {
+    let vars = vars
+      .iter()
+      .map(|(name, value)| (name.to_string(), value.to_string()))
+      .collect::<HashMap<_, _>>();
+    move |name: &str| vars.get(name).cloned().ok_or(VarError::NotPresent)
+  }
+
+  fn resolve(raw: &str) -> Result<Option<String>, String> {
+    resolve_string(raw, &env(&[("PASSWORD", "p@ss")]), &mut 0)
+  }
+
+  #[test]
+  fn plain_strings_are_not_resolved() {
+    assert_eq!(resolve("postgres://db/app"), Ok(None));
+    assert_eq!(resolve("$ {env:X} and {}"), Ok(None));
+  }
+
+  #[test]
+  fn env_references_are_resolved() {
+    let mut references = 0;
+    assert_eq!(
+      resolve_string(
+        "postgres://app:${env:PASSWORD}@db/${env:PASSWORD}",
+        &env(&[("PASSWORD", "p@ss")]),
+        &mut references
+      ),
+      Ok(Some("postgres://app:p@ss@db/p@ss".into()))
+    );
+    assert_eq!(references, 2);
+  }
+
+  #[test]
+  fn file_references_are_resolved_without_trailing_line_break() {
+    let path = std::env::temp_dir().join(format!("cc-secrets-test-{}", std::process::id()));
+    std::fs::write(&path, "token\r\n").unwrap();
+    let resolved = resolve(&format!("${{file:{}}}", path.display()));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(resolved, Ok(Some("token".into())));
+  }
+
+  #[test]
+  fn escaped_references_are_literal() {
+    let mut references = 0;
+    let var = env(&[("HOME", "/root")]);
+    assert_eq!(
+      resolve_string("$${env:HOME}", &var, &mut references),
+      Ok(Some("${env:HOME}".into()))
+    );
+    assert_eq!(
+      resolve_string("a $${ b $${", &var, &mut references),
+      Ok(Some("a ${ b ${".into()))
+    );
+    assert_eq!(references, 0);
+  }
+
+  #[test]
+  fn escape_and_reference_can_be_mixed() {
+    assert_eq!(
+      resolve("$${literal} ${env:PASSWORD} $${env:PASSWORD}"),
+      Ok(Some("${literal} p@ss ${env:PASSWORD}".into()))
+    );
+  }
+
+  #[test]
+  fn nested_references_are_not_resolved() {
+    assert!(resolve("${env:${env:PASSWORD}}").is_err());
+  }
+
+  #[test]
+  fn broken_references_are_errors() {
+    assert!(resolve("${env:PASSWORD").is_err());
+    assert!(resolve("${vault:secret}").is_err());
+    assert!(resolve("${env:MISSING}").is_err());
+  }
+
+  #[test]
+  fn whole_values_are_redacted_longest_first() {
+    let secrets = Secrets {
+      paths: vec!["database.url".into(), "token".into(), "pin".into()],
+      values: vec!["token".into(), "token-2".into(), "42".into()],
+    };
+    assert_eq!(secrets.redact("token-2 and token, not 42"), "*** and ***, not 42");
+
+    let mut document: Value = serde_yaml::from_str("database: { url: x, pool: 4 }").unwrap();
+    secrets.redact_document(&mut document);
+    assert_eq!(
+      document,
+      serde_yaml::from_str::<Value>("database: { url: '***', pool: 4 }").unwrap()
+    );
+  }
+
+  #[test]
+  fn secret_paths_and_values_are_collected() {
+    let mut document: Value =
+      serde_yaml::from_str("a: ${env:S}\nlist: [x, 'postgres://app:${env:S}@db']\nb: $${x}").unwrap();
+    let mut report = ConfigReport::default();
+    let mut secrets = Secrets::default();
+    resolve_value(&mut document, "", &env(&[("S", "s")]), &mut secrets, &mut report);
+    assert!(report.is_ok());
+    assert_eq!(secrets.paths(), ["a", "list[1]"]);
+    assert_eq!(secrets.values, ["s", "postgres://app:s@db"]);
+    assert_eq!(document["b"], Value::from("${x}"));
+    assert_eq!(
+      secrets.redact("connecting to postgres://app:s@db as s"),
+      "connecting to *** as s"
+    );
+  }
+}
@@ -0,0 +1,120 @@
+//! Graceful shutdown module.
+//!
+//! Waits for the termination signals and keeps the hooks which run after all the listeners have stopped, e.g. to
+//! flush the telemetry or to close the database pools.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+type Hook = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Shutdown hooks, available as `GenericServerState::shutdown_hooks`.
+///
+/// ```rust,ignore
+/// let pool = db_pool.clone();
+/// state.shutdown_hooks.register(async move { pool.close().await });
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownHooks(Arc<Mutex<Vec<Hook>>>);
+
+impl ShutdownHooks {
+  /// Registers the hook to run after all the listeners have stopped, before the server's future resolves.
+  pub fn register(&self, hook: impl Future<Output = ()> + Send + 'static) {
+    if let Ok(mut hooks) = self.0.lock() {
+      hooks.push(Box::pin(hook));
+    }
+  }
+
+  /// Runs the registered hooks one by one, in the order of registration.
+  pub(crate) async fn run(&self) {
+    let hooks = self
+      .0
+      .lock()
+      .map(|mut hooks| std::mem::take(&mut *hooks))
+      .unwrap_or_default();
+    if !hooks.is_empty() {
+      tracing::info!("Running {} shutdown hook(s)...", hooks.len());
+    }
+    for hook in hooks {
+      hook.await;
+    }
+  }
+}
+
+/// Waits for the termination signal: SIGINT (Ctrl+C), SIGTERM, SIGQUIT or SIGHUP on Unix and Ctrl+C elsewhere.
+///
+/// Returns the signal's name. If no signal can be listened, waits forever.
+pub async fn wait_for_signal() -> &'static str {
+  #[cfg(unix)]
+  {
+    use std::task::Poll;
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut signals = vec![];
+    for (kind, name) in [
+      (SignalKind::interrupt(), "SIGINT"),
+      (SignalKind::terminate(), "SIGTERM"),
+      (SignalKind::quit(), "SIGQUIT"),
+      (SignalKind::hangup(), "SIGHUP"),
+    ] {
+      match signal(kind) {
+        Ok(signal) => signals.push((signal, name)),
+        Err(e) => tracing::error!("Failed to listen {}: {}", name, e),
+      }
+    }
+    std::future::poll_fn(|cx| {
+      for (signal, name) in signals.iter_mut() {
+        if signal.poll_recv(cx).is_ready() {
+          return Poll::Ready(*name);
+        }
+      }
+      Poll::Pending
+    })
+    .await
+  }
+
+  #[cfg(not(unix))]
+  {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+      tracing::error!("Failed to listen Ctrl+C: {}", e);
+      std::future::pending::<()>().await;
+    }
+    "Ctrl+C"
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn hooks_run_once_in_the_order_of_registration() {
+    let hooks = ShutdownHooks::default();
+    let order = Arc::new(Mutex::new(vec![]));
+    for index in 0..3 {
+      let order = order.clone();
+      hooks.register(async move {
+        tokio::task::yield_now().await;
+        order.lock().unwrap().push(index);
+      });
+    }
+
+    hooks.clone().run().await;
+    assert_eq!(*order.lock().unwrap(), [0, 1, 2]);
+    hooks.run().await;
+    assert_eq!(*order.lock().unwrap(), [0, 1, 2]);
+  }
+
+  #[tokio::test]
+  async fn hooks_registered_after_run_run_next_time() {
+    let hooks = ShutdownHooks::default();
+    hooks.run().await;
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    hooks.register(async move {
+      let _ = tx.send(());
+    });
+    hooks.run().await;
+    assert!(rx.await.is_ok());
+  }
+}
@@ -1,16 +1,20 @@
 //! Startup module.
 
-use cc_utils::prelude::MResult;
+use cc_utils::prelude::{ErrorResponse, MResult};
 use salvo::prelude::*;
 
-use salvo::conn::rustls::{Keycert, RustlsConfig};
+use salvo::http::header::{HOST, STRICT_TRANSPORT_SECURITY};
+use salvo::http::uri::Scheme;
+use salvo::http::{HeaderName, HeaderValue};
 use salvo::server::ServerHandle;
 use std::future::Future;
 use std::pin::Pin;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-#[cfg(feature = "http3")]
-use salvo::http::HeaderValue;
+#[cfg(unix)]
+use salvo::conn::tcp::TcpAcceptor;
 #[cfg(feature = "http3")]
 use salvo::http::header::ALT_SVC;
 
@@ -19,7 +23,30 @@ use salvo::oapi::SecurityScheme;
 #[cfg(feature = "oapi")]
 use salvo::oapi::security::Http;
 
-use crate::generic_setup::{GenericServerState, GenericSetup, StartupVariant};
+#[cfg(feature = "cors")]
+use salvo::Handler;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+use crate::client_cert::{ClientCertHoop, ClientCertListener, ClientCerts};
+#[cfg(feature = "metrics")]
+use crate::generic_setup::DEFAULT_METRICS_PATH;
+use crate::generic_setup::{
+  DEFAULT_HEALTH_CHECK_TIMEOUT, GenericServerState, GenericSetup, GenericValues, SecurityHeaders, StartupVariant,
+  TlsSettings,
+};
+use crate::health::{HealthChecks, health_router};
+use crate::hot_reload::watch_generic_config;
+#[cfg(feature = "metrics")]
+use crate::metrics::{HttpMetricsHoop, metrics_router};
+use crate::shutdown::wait_for_signal;
+#[cfg(unix)]
+use crate::systemd::{self, ListenFd, take_listen_fd};
+#[cfg(feature = "http3")]
+use crate::tls::quinn_configs;
+use crate::tls::{TlsFiles, server_configs, watch_tls_config};
+#[cfg(unix)]
+use crate::unix_socket::{bind_socket, forward_listener, remove_private_dir, remove_socket, remove_stale_socket};
 
 #[cfg(feature = "http3")]
 #[handler]
@@ -27,10 +54,8 @@ use crate::generic_setup::{GenericServerState, GenericSetup, StartupVariant};
 ///
 /// Usage is `router.hoop(h3_header)`.
 pub async fn h3_header(depot: &mut Depot, res: &mut Response) {
-  use crate::generic_setup::GenericValues;
-
   let server_port = match depot.obtain::<GenericValues>() {
-    Ok(app_config) => app_config.server_port.unwrap(),
+    Ok(app_config) => app_config.startup_variant.server_port().unwrap_or(443),
     Err(_) => 443,
   };
 
@@ -43,20 +68,36 @@ pub async fn h3_header(depot: &mut Depot, res: &mut Response) {
     .unwrap();
 }
 
-pub fn get_root_router_autoinject<T: GenericSetup + Send + Sync + Clone + 'static>(
+/// Returns preconfigured root router with application config and state injected in `affix_state`.
+///
+/// If `hot_reload` is enabled, also injects `ConfigWatch<T>` with the live configuration; see `watch_generic_config`.
+/// Installs the security headers' hoop if `security_headers` is set.
+pub fn get_root_router_autoinject<T: GenericSetup + DeserializeOwned + Send + Sync + Clone + 'static>(
   app_state: &GenericServerState,
   app_config: T,
 ) -> Router {
-  #[allow(unused_mut)]
-  let mut router = Router::new().hoop(affix_state::inject(app_state.clone()).inject(app_config));
+  let hot_reload = app_config.generic_values().hot_reload.is_some_and(|v| v);
+  let mut router = Router::new().hoop(affix_state::inject(app_state.clone()).inject(app_config.clone()));
+  router = hoop_security_headers(router, app_config.generic_values());
+
+  if app_config.generic_values().admin.is_none() {
+    router = push_health_router(router, app_state, app_config.generic_values());
+    #[cfg(feature = "metrics")]
+    {
+      router = push_metrics_router(router, app_config.generic_values());
+    }
+  }
+  router = push_host_routers(router, app_state);
 
-  #[cfg(all(feature = "http3", feature = "acme"))]
-  if app_state.startup_variant == StartupVariant::QuinnAcme {
-    router = router.hoop(h3_header);
+  if hot_reload {
+    match watch_generic_config(&app_config, app_state) {
+      Ok(config_watch) => router = router.hoop(affix_state::inject(config_watch)),
+      Err(_) => tracing::error!("Failed to watch the configuration files; hot reload is disabled."),
+    }
   }
 
   #[cfg(feature = "http3")]
-  if app_state.startup_variant == StartupVariant::Quinn || app_state.startup_variant == StartupVariant::QuinnOnly {
+  if app_state.startup_variant.is_quic() {
     router = router.hoop(h3_header);
   }
 
@@ -66,24 +107,236 @@ pub fn get_root_router_autoinject<T: GenericSetup + Send + Sync + Clone + 'stati
 /// Returns preconfigured root router to use.
 ///
 /// Usually it installs application config and state in `affix_state` and installs `h3_header` for switching protocol to QUIC, if used.
-#[allow(unused_variables)]
+///
+/// Installs the security headers' hoop if `security_headers` is set and mounts the virtual hosts' routers registered in
+/// `GenericServerState::host_routers`.
 pub fn get_root_router(app_state: &GenericServerState) -> Router {
-  #[allow(unused_mut)]
-  let mut router = Router::new();
-
-  #[cfg(all(feature = "http3", feature = "acme"))]
-  if app_state.startup_variant == StartupVariant::QuinnAcme {
-    router = router.hoop(h3_header);
+  let values = app_state.live_values.borrow().clone();
+  let mut router = hoop_security_headers(Router::new(), &values);
+  if values.admin.is_none() {
+    router = push_health_router(router, app_state, &values);
+    #[cfg(feature = "metrics")]
+    {
+      router = push_metrics_router(router, &values);
+    }
   }
+  router = push_host_routers(router, app_state);
 
   #[cfg(feature = "http3")]
-  if app_state.startup_variant == StartupVariant::Quinn || app_state.startup_variant == StartupVariant::QuinnOnly {
+  if app_state.startup_variant.is_quic() {
     router = router.hoop(h3_header);
   }
 
   router
 }
 
+/// Installs the security headers' hoop if `security_headers` is set.
+fn hoop_security_headers(router: Router, values: &GenericValues) -> Router {
+  match &values.security_headers {
+    Some(settings) => router.hoop(SecurityHeadersHoop::new(settings)),
+    None => router,
+  }
+}
+
+/// Mounts `/healthz` and `/readyz` if `allow_health_endpoints` is set.
+fn push_health_router(router: Router, app_state: &GenericServerState, values: &GenericValues) -> Router {
+  if !values.allow_health_endpoints.is_some_and(|v| v) {
+    return router;
+  }
+  let timeout = Duration::from_secs(values.health_check_timeout.unwrap_or(DEFAULT_HEALTH_CHECK_TIMEOUT));
+  router.push(health_router(app_state.health_checks.clone(), timeout))
+}
+
+#[cfg(feature = "metrics")]
+/// Mounts the metrics endpoint if `allow_metrics` is set.
+fn push_metrics_router(router: Router, values: &GenericValues) -> Router {
+  if !values.allow_metrics.is_some_and(|v| v) {
+    return router;
+  }
+  router.push(metrics_router(
+    values.metrics_path.as_deref().unwrap_or(DEFAULT_METRICS_PATH),
+  ))
+}
+
+/// Routers of the virtual hosts, available as `GenericServerState::host_routers`.
+///
+/// Register them before building the root router; `get_root_router` and `get_root_router_autoinject` mount each one
+/// with `host_router` before the routes common for all the hosts:
+///
+/// ```rust,ignore
+/// let state = load_generic_state(&setup).await?;
+/// state.host_routers.register("api.example.com", api_router());
+/// state.host_routers.register("www.example.com", www_router());
+/// let router = get_root_router(&state).push(common_router());
+/// ```
+///
+/// The routers are moved into the root router built first, so build the root router once per state; the routers
+/// registered later are mounted by the next one.
+#[derive(Clone, Default)]
+pub struct HostRouters {
+  routers: Arc<std::sync::Mutex<Vec<(String, Router)>>>,
+  mounted: Arc<AtomicBool>,
+}
+
+impl HostRouters {
+  /// Registers the router serving the requests to the host.
+  pub fn register(&self, host: impl Into<String>, router: Router) {
+    if let Ok(mut routers) = self.routers.lock() {
+      routers.push((host.into(), router));
+    }
+  }
+
+  /// Takes the registered routers to mount them; warns if they have already been mounted into another root router.
+  fn take(&self) -> Vec<(String, Router)> {
+    let routers = self
+      .routers
+      .lock()
+      .map(|mut routers| std::mem::take(&mut *routers))
+      .unwrap_or_default();
+    if self.mounted.swap(true, Ordering::Relaxed) && routers.is_empty() {
+      tracing::warn!("The virtual hosts' routers are already mounted into another root router; this one has none.");
+    }
+    routers
+  }
+}
+
+/// Mounts the registered virtual hosts' routers.
+///
+/// Warns about the hosts which the TLS listeners have no certificate for in `ssl_hosts`, since their clients get the
+/// default one.
+fn push_host_routers(mut router: Router, app_state: &GenericServerState) -> Router {
+  let ssl_hosts = std::iter::once(&app_state.startup_variant)
+    .chain(&app_state.listeners)
+    .filter_map(StartupVariant::ssl_hosts)
+    .filter(|hosts| !hosts.is_empty())
+    .collect::<Vec<_>>();
+  for (host, host_routes) in app_state.host_routers.take() {
+    if !ssl_hosts.is_empty()
+      && !ssl_hosts
+        .iter()
+        .any(|hosts| hosts.keys().any(|name| name.eq_ignore_ascii_case(&host)))
+    {
+      tracing::warn!(
+        "Host `{}` has its own router, but no certificate in `ssl_hosts`; it's served with the default one.",
+        host
+      );
+    }
+    router = router.push(host_router(host, host_routes));
+  }
+  router
+}
+
+/// Returns the router which serves only the requests to the host, e.g. to one of the virtual hosts with their own
+/// certificates in `ssl_hosts`.
+///
+/// The host is taken from the request URI or the `Host` header, without the port, and is compared case-insensitively.
+/// Push the virtual hosts' routers before the routes common for all the hosts:
+///
+/// ```rust,ignore
+/// let router = get_root_router(&state)
+///   .push(host_router("api.example.com", api_router()))
+///   .push(host_router("www.example.com", www_router()));
+/// ```
+///
+/// Routers registered in `GenericServerState::host_routers` are wrapped with it by `get_root_router`.
+pub fn host_router(host: impl Into<String>, router: Router) -> Router {
+  let host = host.into();
+  Router::new()
+    .filter_fn(move |req, _| request_host(req).is_some_and(|request_host| request_host.eq_ignore_ascii_case(&host)))
+    .push(router)
+}
+
+/// Returns the host without the port and the IPv6 brackets: from the URI, where HTTP/2 and HTTP/3 requests have
+/// `:authority`, or from the `Host` header.
+fn request_host(req: &Request) -> Option<&str> {
+  let host = match req.uri().host() {
+    Some(host) => host,
+    None => {
+      let authority = req.headers().get(HOST)?.to_str().ok()?;
+      match authority.find(']') {
+        Some(end) if authority.starts_with('[') => &authority[..=end],
+        _ => authority.split(':').next()?,
+      }
+    }
+  };
+  Some(
+    host
+      .strip_prefix('[')
+      .and_then(|host| host.strip_suffix(']'))
+      .unwrap_or(host),
+  )
+}
+
+#[cfg(feature = "cors")]
+fn cors_handler(domain: &str) -> salvo::cors::CorsHandler {
+  salvo::cors::Cors::new()
+    .allow_origin(domain)
+    .allow_credentials(domain != "*")
+    .allow_headers(vec![
+      "Authorization",
+      "Accept",
+      "Access-Control-Allow-Headers",
+      "Content-Type",
+      "Origin",
+      "X-Requested-With",
+      "Cookie",
+    ])
+    .expose_headers(vec!["Set-Cookie"])
+    .allow_methods(vec![
+      salvo::http::Method::GET,
+      salvo::http::Method::POST,
+      salvo::http::Method::PUT,
+      salvo::http::Method::PATCH,
+      salvo::http::Method::DELETE,
+      salvo::http::Method::OPTIONS,
+    ])
+    .into_handler()
+}
+
+#[cfg(feature = "cors")]
+/// CORS handler which follows `allow_cors_domain` of the live configuration.
+///
+/// The underlying `CorsHandler` is rebuilt only when the domain changes.
+struct LiveCors {
+  values: tokio::sync::watch::Receiver<GenericValues>,
+  current: std::sync::RwLock<Option<(String, Arc<salvo::cors::CorsHandler>)>>,
+}
+
+#[cfg(feature = "cors")]
+impl LiveCors {
+  fn new(values: tokio::sync::watch::Receiver<GenericValues>) -> Self {
+    Self {
+      values,
+      current: std::sync::RwLock::new(None),
+    }
+  }
+
+  fn handler(&self, domain: String) -> Arc<salvo::cors::CorsHandler> {
+    if let Ok(current) = self.current.read()
+      && let Some((current_domain, cors)) = current.as_ref()
+      && *current_domain == domain
+    {
+      return cors.clone();
+    }
+    let cors = Arc::new(cors_handler(&domain));
+    if let Ok(mut current) = self.current.write() {
+      *current = Some((domain, cors.clone()));
+    }
+    cors
+  }
+}
+
+#[cfg(feature = "cors")]
+#[handler]
+impl LiveCors {
+  async fn handle(&self, req: &mut Request, depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let domain = self.values.borrow().allow_cors_domain.clone();
+    if let Some(domain) = domain {
+      self.handler(domain).handle(req, depot, res, ctrl).await;
+    }
+  }
+}
+
 #[cfg(any(feature = "oapi", feature = "acme"))]
 #[allow(clippy::mut_from_ref, invalid_reference_casting)]
 unsafe fn make_mut<T>(reference: &T) -> &mut T {
@@ -92,43 +345,346 @@ unsafe fn make_mut<T>(reference: &T) -> &mut T {
   unsafe { &mut *mut_ptr }
 }
 
+/// Builds the OpenAPI document of the router with the bearer security scheme.
+#[cfg(feature = "oapi")]
+pub fn openapi_doc(name: &str, version: &str, router: &Router) -> OpenApi {
+  OpenApi::new(name, version)
+    .add_security_scheme(
+      "bearer",
+      SecurityScheme::Http(Http::new(salvo::oapi::security::HttpAuthScheme::Bearer).bearer_format("JSON")),
+    )
+    .merge_router(router)
+}
+
 #[cfg(feature = "force-https")]
+/// Starts plain HTTP server on `0.0.0.0:{listen_port}` redirecting all the requests to HTTPS on `redirect_port`.
+///
+/// Consider setting `redirect_http_port` instead: `start` will serve the redirect next to the main listener and stop
+/// it together with the main one.
 pub async fn start_force_https_redirect(
   listen_port: u16,
   redirect_port: u16,
+) -> MResult<(Pin<Box<dyn Future<Output = ()> + Send>>, ServerHandle)> {
+  serve_https_redirect("0.0.0.0", listen_port, redirect_port).await
+}
+
+#[cfg(feature = "force-https")]
+async fn serve_https_redirect(
+  host: &str,
+  listen_port: u16,
+  redirect_port: u16,
 ) -> MResult<(Pin<Box<dyn Future<Output = ()> + Send>>, ServerHandle)> {
   let service = Service::new(Router::new()).hoop(ForceHttps::new().https_port(redirect_port));
-  let acceptor = TcpListener::new(format!("0.0.0.0:{}", listen_port)).bind().await;
+  let acceptor = TcpListener::new(format!("{}:{}", host, listen_port)).bind().await;
   let server = Server::new(acceptor);
   let handle = server.handle();
   let server = Box::pin(server.serve(service));
   Ok((server, handle))
 }
 
+/// Adds the security headers to the responses; HSTS is added to the responses sent over HTTPS or QUIC only.
+///
+/// The handlers can override the headers, as they are set before the handlers are called.
+struct SecurityHeadersHoop {
+  headers: Vec<(HeaderName, HeaderValue)>,
+  hsts: Option<HeaderValue>,
+}
+
+impl SecurityHeadersHoop {
+  /// Skips the values which can't be sent as headers; `check_generic_config` reports them.
+  fn new(settings: &SecurityHeaders) -> Self {
+    let headers = settings
+      .headers()
+      .into_iter()
+      .filter_map(|(name, value)| Some((HeaderName::from_static(name), HeaderValue::from_str(&value).ok()?)))
+      .collect();
+    let hsts = settings
+      .hsts_policy()
+      .and_then(|hsts| HeaderValue::from_str(&hsts.header_value()).ok());
+    Self { headers, hsts }
+  }
+}
+
+#[handler]
+impl SecurityHeadersHoop {
+  async fn handle(&self, req: &mut Request, res: &mut Response) {
+    for (name, value) in &self.headers {
+      res.headers_mut().insert(name.clone(), value.clone());
+    }
+    if let Some(hsts) = &self.hsts
+      && req.scheme() == &Scheme::HTTPS
+    {
+      res.headers_mut().insert(STRICT_TRANSPORT_SECURITY, hsts.clone());
+    }
+  }
+}
+
+/// Creates another service sharing the router, hoops and catcher with the given one.
+fn share_service(service: &Service) -> Service {
+  let mut shared = Service::new(service.router.clone());
+  shared.catcher = service.catcher.clone();
+  shared.hoops = service.hoops.clone();
+  shared.allowed_media_types = service.allowed_media_types.clone();
+  shared
+}
+
+/// Binds the listener described by the startup variant and starts serving the service on it.
+async fn serve_variant(
+  variant: StartupVariant,
+  service: Service,
+  tls: &TlsSettings,
+  client_certs: &ClientCerts,
+) -> MResult<(Pin<Box<dyn Future<Output = ()> + Send>>, ServerHandle)> {
+  let handle;
+
+  if variant.is_acme() && *tls != TlsSettings::default() {
+    return Err(
+      ErrorResponse::from("`tls` settings can't be applied to the ACME listeners.")
+        .with_500_pub()
+        .build(),
+    );
+  }
+
+  #[cfg(feature = "http3")]
+  if variant.is_quic() && variant.verifies_clients() {
+    return Err(
+      ErrorResponse::from("`ssl_client_ca_path` isn't supported by the QUIC listeners; use `https_only`.")
+        .with_500_pub()
+        .build(),
+    );
+  }
+
+  let server = match variant {
+    StartupVariant::HttpLocalhost { server_port } => {
+      let acceptor = TcpListener::new(format!("127.0.0.1:{}", server_port)).bind().await;
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service)) as Pin<Box<dyn Future<Output = ()> + Send>>
+    }
+    StartupVariant::UnsafeHttp {
+      server_host,
+      server_port,
+    } => {
+      let acceptor = TcpListener::new(format!("{}:{}", server_host, server_port))
+        .bind()
+        .await;
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service))
+    }
+    #[cfg(feature = "acme")]
+    StartupVariant::HttpsAcme {
+      server_host,
+      server_port,
+      acme_domain,
+    } => {
+      let acceptor = TcpListener::new(format!("{}:{}", server_host, server_port))
+        .acme()
+        .cache_path("tmp/letsencrypt")
+        .add_domain(acme_domain)
+        .bind()
+        .await;
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service))
+    }
+    StartupVariant::HttpsOnly {
+      server_host,
+      server_port,
+      ssl_key_path,
+      ssl_crt_path,
+      ssl_client_ca_path,
+      ssl_client_auth,
+      ssl_hosts,
+    } => {
+      let verifies_clients = ssl_client_ca_path.is_some();
+      let tls_configs = watch_tls_config(TlsFiles {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ssl_client_auth,
+        ssl_hosts,
+        tls: tls.clone(),
+      })?;
+      let listener = TcpListener::new(format!("{}:{}", server_host, server_port));
+
+      if verifies_clients {
+        let server = Server::new(
+          ClientCertListener::new(listener, tls_configs, client_certs.clone())
+            .bind()
+            .await,
+        );
+        handle = server.handle();
+        Box::pin(server.serve(service)) as Pin<Box<dyn Future<Output = ()> + Send>>
+      } else {
+        let server = Server::new(listener.rustls(server_configs(tls_configs)).bind().await);
+        handle = server.handle();
+        Box::pin(server.serve(service))
+      }
+    }
+    #[cfg(all(feature = "http3", feature = "acme"))]
+    StartupVariant::QuinnAcme {
+      server_host,
+      server_port,
+      acme_domain,
+    } => {
+      let acceptor = TcpListener::new(format!("{}:{}", server_host, server_port))
+        .acme()
+        .cache_path("tmp/letsencrypt")
+        .add_domain(acme_domain)
+        .quinn(format!("{}:{}", server_host, server_port))
+        .bind()
+        .await;
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service))
+    }
+    #[cfg(feature = "http3")]
+    StartupVariant::Quinn {
+      server_host,
+      server_port,
+      ssl_key_path,
+      ssl_crt_path,
+      ssl_client_ca_path,
+      ssl_client_auth,
+      ssl_hosts,
+    } => {
+      let tls_configs = watch_tls_config(TlsFiles {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ssl_client_auth,
+        ssl_hosts,
+        tls: tls.clone(),
+      })?;
+      let listener = TcpListener::new(format!("{}:{}", server_host, server_port));
+      let acceptor = QuinnListener::new(
+        quinn_configs(tls_configs.clone(), tls)?,
+        format!("{}:{}", server_host, server_port),
+      )
+      .join(listener.rustls(server_configs(tls_configs)))
+      .bind()
+      .await;
+
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service))
+    }
+    #[cfg(feature = "http3")]
+    StartupVariant::QuinnOnly {
+      server_host,
+      server_port,
+      ssl_key_path,
+      ssl_crt_path,
+      ssl_client_ca_path,
+      ssl_client_auth,
+      ssl_hosts,
+    } => {
+      let tls_configs = watch_tls_config(TlsFiles {
+        ssl_crt_path,
+        ssl_key_path,
+        ssl_client_ca_path,
+        ssl_client_auth,
+        ssl_hosts,
+        tls: tls.clone(),
+      })?;
+      let acceptor = QuinnListener::new(
+        quinn_configs(tls_configs, tls)?,
+        format!("{}:{}", server_host, server_port),
+      )
+      .bind()
+      .await;
+
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(server.serve(service))
+    }
+    #[cfg(unix)]
+    StartupVariant::UnixSocket {
+      socket_path,
+      socket_mode,
+      socket_owner,
+    } => {
+      remove_stale_socket(&socket_path)?;
+      let acceptor = bind_socket(&socket_path, socket_mode.as_deref(), socket_owner.as_deref()).await?;
+
+      let server = Server::new(acceptor);
+      handle = server.handle();
+      Box::pin(async move {
+        server.serve(service).await;
+        remove_socket(&socket_path);
+      })
+    }
+    #[cfg(unix)]
+    StartupVariant::SystemdSocket { listen_fd_name } => match take_listen_fd(listen_fd_name.as_deref())? {
+      ListenFd::Tcp(listener) => {
+        let server = Server::new(TcpAcceptor::try_from(listener)?);
+        handle = server.handle();
+        Box::pin(server.serve(service)) as Pin<Box<dyn Future<Output = ()> + Send>>
+      }
+      ListenFd::Unix(listener) => {
+        let (acceptor, private_dir) = forward_listener(listener).await?;
+        let server = Server::new(acceptor);
+        handle = server.handle();
+        Box::pin(async move {
+          server.serve(service).await;
+          remove_private_dir(&private_dir);
+        })
+      }
+    },
+  };
+
+  Ok((server, handle))
+}
+
 pub async fn start_with_service(
   app_state: GenericServerState,
   app_config: &impl GenericSetup,
-  #[allow(unused_mut)] mut service: Service,
+  service: Service,
 ) -> MResult<(Pin<Box<dyn Future<Output = ()> + Send>>, ServerHandle)> {
+  let (server, handle, _) = serve_all(app_state, app_config, service).await?;
+  Ok((server, handle))
+}
+
+/// Starts all the listeners; returns the server's future, the main listener's handle and the handles of all the
+/// listeners, including the main one, the admin and the redirect ones.
+async fn serve_all(
+  app_state: GenericServerState,
+  app_config: &impl GenericSetup,
+  #[allow(unused_mut)] mut service: Service,
+) -> MResult<(
+  Pin<Box<dyn Future<Output = ()> + Send>>,
+  ServerHandle,
+  Vec<ServerHandle>,
+)> {
   tracing::info!("Server is starting...");
 
   let app_config = app_config.generic_values();
 
   if let Some(bin) = app_config.auto_migrate_bin.as_ref() {
-    Command::new(bin).spawn()?;
+    Command::new(bin)
+      .env_remove("LISTEN_PID")
+      .env_remove("LISTEN_FDS")
+      .env_remove("LISTEN_FDNAMES")
+      .spawn()?;
+  }
+
+  let mut admin_router = Router::new();
+  if app_config.admin.is_some() {
+    admin_router = push_health_router(admin_router, &app_state, app_config);
+    #[cfg(feature = "metrics")]
+    {
+      admin_router = push_metrics_router(admin_router, app_config);
+    }
   }
 
   #[cfg(feature = "oapi")]
   if app_config.allow_oapi_access.is_some_and(|v| v) {
-    let doc = OpenApi::new(
+    let doc = openapi_doc(
       app_config.oapi_name.as_ref().unwrap(),
       app_config.oapi_ver.as_ref().unwrap(),
-    )
-    .add_security_scheme(
-      "bearer",
-      SecurityScheme::Http(Http::new(salvo::oapi::security::HttpAuthScheme::Bearer).bearer_format("JSON")),
-    )
-    .merge_router(&service.router);
+      &service.router,
+    );
 
     let oapi_endpoint = if let Some(ftype) = app_config.oapi_frontend_type.as_ref() {
       match ftype.as_str() {
@@ -168,185 +724,93 @@ pub async fn start_with_service(
       router = router.push(oapi);
     }
 
-    unsafe {
-      let service_router = make_mut(service.router.as_ref());
-      service_router.routers_mut().insert(0, router);
+    if app_config.admin.is_some() {
+      admin_router = admin_router.push(router);
+    } else {
+      unsafe {
+        let service_router = make_mut(service.router.as_ref());
+        service_router.routers_mut().insert(0, router);
+      }
     }
 
     tracing::info!("API is available on {}", app_config.oapi_api_addr.as_ref().unwrap());
   }
 
+  #[cfg(feature = "metrics")]
+  if app_config.records_metrics() {
+    service.hoops.insert(0, Arc::new(HttpMetricsHoop));
+  }
+
   #[cfg(feature = "cors")]
-  if let Some(domain) = &app_config.allow_cors_domain {
-    let cors = salvo::cors::Cors::new()
-      .allow_origin(domain)
-      .allow_credentials(domain.as_str() != "*")
-      .allow_headers(vec![
-        "Authorization",
-        "Accept",
-        "Access-Control-Allow-Headers",
-        "Content-Type",
-        "Origin",
-        "X-Requested-With",
-        "Cookie",
-      ])
-      .expose_headers(vec!["Set-Cookie"])
-      .allow_methods(vec![
-        salvo::http::Method::GET,
-        salvo::http::Method::POST,
-        salvo::http::Method::PUT,
-        salvo::http::Method::PATCH,
-        salvo::http::Method::DELETE,
-        salvo::http::Method::OPTIONS,
-      ])
-      .into_handler();
-
-    service = service.hoop(cors);
+  if app_config.allow_cors_domain.is_some() || app_config.hot_reload.is_some_and(|v| v) {
+    service = service.hoop(LiveCors::new(app_state.live_values.subscribe()));
   }
 
-  let handle;
+  let client_certs = ClientCerts::default();
+  if std::iter::once(&app_state.startup_variant)
+    .chain(&app_state.listeners)
+    .any(StartupVariant::verifies_clients)
+  {
+    service = service.hoop(ClientCertHoop(client_certs.clone()));
+  }
 
-  let server = match app_state.startup_variant {
-    StartupVariant::HttpLocalhost => {
-      let acceptor = TcpListener::new(format!("127.0.0.1:{}", app_config.server_port.unwrap()))
-        .bind()
-        .await;
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service)) as Pin<Box<dyn Future<Output = ()> + Send>>
-    }
-    StartupVariant::UnsafeHttp => {
-      let acceptor = TcpListener::new(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .bind()
-      .await;
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service))
-    }
-    #[cfg(feature = "acme")]
-    StartupVariant::HttpsAcme => {
-      let acceptor = TcpListener::new(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .acme()
-      .cache_path("tmp/letsencrypt")
-      .add_domain(app_config.acme_domain.as_ref().unwrap())
-      .bind()
-      .await;
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service))
-    }
-    StartupVariant::HttpsOnly => {
-      let rustls_config = RustlsConfig::new(
-        Keycert::new()
-          .cert_from_path(app_config.ssl_crt_path.as_ref().unwrap())?
-          .key_from_path(app_config.ssl_key_path.as_ref().unwrap())?,
+  let grace_period = app_config.shutdown_grace_period.map(Duration::from_secs);
+  let shutdown_hooks = app_state.shutdown_hooks.clone();
+
+  let mut followers = vec![];
+  #[cfg(feature = "force-https")]
+  if let Some(redirect_http_port) = app_config.redirect_http_port {
+    let Some(variant) = std::iter::once(&app_state.startup_variant)
+      .chain(&app_state.listeners)
+      .find(|variant| variant.is_https())
+    else {
+      return Err(
+        ErrorResponse::from("`redirect_http_port` needs a listener serving HTTPS over TCP; `quinn_only` doesn't.")
+          .with_500_pub()
+          .build(),
       );
-      let listener = TcpListener::new(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .rustls(rustls_config.clone())
-      .bind()
-      .await;
-
-      let server = Server::new(listener);
-      handle = server.handle();
-      Box::pin(server.serve(service))
+    };
+    let https_port = variant.server_port().unwrap_or(443);
+    let host = variant.server_host().unwrap_or("0.0.0.0");
+    followers.push(serve_https_redirect(host, redirect_http_port, https_port).await?);
+    tracing::info!("Redirecting HTTP requests on port {} to HTTPS", redirect_http_port);
+  }
+  if let Some(admin) = app_config.admin.clone() {
+    let admin_addr = admin.socket_addr();
+    followers.push(serve_variant(admin, Service::new(admin_router), &app_config.tls, &client_certs).await?);
+    tracing::info!("Admin endpoints are available on {}", admin_addr);
+  }
+  for listener in app_state.listeners {
+    followers.push(serve_variant(listener, share_service(&service), &app_config.tls, &client_certs).await?);
+  }
+  let (server, handle) = serve_variant(app_state.startup_variant, service, &app_config.tls, &client_certs).await?;
+  let all_handles = std::iter::once(handle.clone())
+    .chain(followers.iter().map(|(_, handle)| handle.clone()))
+    .collect();
+
+  #[cfg(unix)]
+  let watchdog = systemd::notify_ready();
+
+  let server: Pin<Box<dyn Future<Output = ()> + Send>> = Box::pin(async move {
+    let (servers, handles): (Vec<_>, Vec<_>) = followers.into_iter().unzip();
+    let tasks = servers.into_iter().map(tokio::spawn).collect::<Vec<_>>();
+    server.await;
+    #[cfg(unix)]
+    {
+      systemd::notify_stopping();
+      if let Some(watchdog) = watchdog {
+        watchdog.abort();
+      }
     }
-    #[cfg(all(feature = "http3", feature = "acme"))]
-    StartupVariant::QuinnAcme => {
-      let acceptor = TcpListener::new(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .acme()
-      .cache_path("tmp/letsencrypt")
-      .add_domain(app_config.acme_domain.as_ref().unwrap())
-      .quinn(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .bind()
-      .await;
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service))
+    for handle in handles {
+      handle.stop_graceful(grace_period);
     }
-    #[cfg(feature = "http3")]
-    StartupVariant::Quinn => {
-      let rustls_config = RustlsConfig::new(
-        Keycert::new()
-          .cert_from_path(app_config.ssl_crt_path.as_ref().unwrap())?
-          .key_from_path(app_config.ssl_key_path.as_ref().unwrap())?,
-      );
-      let listener = TcpListener::new(format!(
-        "{}:{}",
-        app_config.server_host.as_ref().unwrap(),
-        app_config.server_port.unwrap()
-      ))
-      .rustls(rustls_config.clone());
-
-      let quinn_config = RustlsConfig::new(
-        Keycert::new()
-          .cert_from_path(app_config.ssl_crt_path.as_ref().unwrap())?
-          .key_from_path(app_config.ssl_key_path.as_ref().unwrap())?,
-      )
-      .alpn_protocols(vec!["h3".as_bytes().to_owned()])
-      .build_quinn_config()?;
-      let acceptor = QuinnListener::new(
-        quinn_config,
-        format!(
-          "{}:{}",
-          app_config.server_host.as_ref().unwrap(),
-          app_config.server_port.unwrap()
-        ),
-      )
-      .join(listener)
-      .bind()
-      .await;
-
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service))
+    for task in tasks {
+      let _ = task.await;
     }
-    #[cfg(feature = "http3")]
-    StartupVariant::QuinnOnly => {
-      let quinn_config = RustlsConfig::new(
-        Keycert::new()
-          .cert_from_path(app_config.ssl_crt_path.as_ref().unwrap())?
-          .key_from_path(app_config.ssl_key_path.as_ref().unwrap())?,
-      )
-      .alpn_protocols(vec!["h3".as_bytes().to_owned()])
-      .build_quinn_config()?;
-      let acceptor = QuinnListener::new(
-        quinn_config,
-        format!(
-          "{}:{}",
-          app_config.server_host.as_ref().unwrap(),
-          app_config.server_port.unwrap()
-        ),
-      )
-      .bind()
-      .await;
-
-      let server = Server::new(acceptor);
-      handle = server.handle();
-      Box::pin(server.serve(service))
-    }
-  };
-
-  Ok((server, handle))
+    shutdown_hooks.run().await;
+  });
+  Ok((server, handle, all_handles))
 }
 
 /// Starts the server according to the startup variant provided with the custom shutdown.
@@ -364,14 +828,89 @@ pub async fn start(
   app_config: &impl GenericSetup,
   router: Router,
 ) -> MResult<(Pin<Box<dyn Future<Output = ()> + Send>>, ServerHandle)> {
-  let (fut, handle) = start_clean(app_state, app_config, router).await?;
-  let ctrl_c_handle = handle.clone();
-  tokio::spawn(async move { shutdown_signal(ctrl_c_handle).await });
+  let data = app_config.generic_values();
+  let pre_stop_delay = Duration::from_secs(data.pre_stop_delay.unwrap_or_default());
+  let grace_period = data.shutdown_grace_period.map(Duration::from_secs);
+
+  let health_checks = app_state.health_checks.clone();
+
+  let (fut, handle, handles) = serve_all(app_state, app_config, Service::new(router)).await?;
+  tokio::spawn(shutdown_signal(handles, health_checks, pre_stop_delay, grace_period));
   Ok((fut, handle))
 }
 
-pub async fn shutdown_signal(handle: ServerHandle) {
-  tokio::signal::ctrl_c().await.unwrap();
-  tracing::info!("Shutdown with Ctrl+C requested.");
-  handle.stop_graceful(None);
+/// Waits for the termination signal and stops the server gracefully: turns `/readyz` to not ready and keeps serving
+/// for `pre_stop_delay`, then stops all the listeners at once and waits for the running requests for `grace_period` at
+/// most.
+///
+/// `start` calls it with the handles of all the listeners; with `start_clean`, pass the returned handle, e.g.
+/// `shutdown_signal([handle], health_checks, Duration::ZERO, None)`.
+pub async fn shutdown_signal(
+  handles: impl IntoIterator<Item = ServerHandle>,
+  health_checks: HealthChecks,
+  pre_stop_delay: Duration,
+  grace_period: Option<Duration>,
+) {
+  let signal = wait_for_signal().await;
+  tracing::info!("Shutdown with {} requested.", signal);
+  health_checks.set_shutting_down();
+  #[cfg(unix)]
+  systemd::notify_stopping();
+  if !pre_stop_delay.is_zero() {
+    tracing::info!("Stopping the listeners in {} s...", pre_stop_delay.as_secs());
+    tokio::time::sleep(pre_stop_delay).await;
+  }
+  for handle in handles {
+    handle.stop_graceful(grace_period);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(uri: &str, host: Option<&str>) -> Request {
+    let mut req = Request::new();
+    req.set_uri(uri.parse().unwrap());
+    if let Some(host) = host {
+      req.headers_mut().insert(HOST, HeaderValue::from_str(host).unwrap());
+    }
+    req
+  }
+
+  #[test]
+  fn host_is_taken_without_port() {
+    assert_eq!(request_host(&request("/", Some("Example.com"))), Some("Example.com"));
+    assert_eq!(
+      request_host(&request("/", Some("example.com:8443"))),
+      Some("example.com")
+    );
+    assert_eq!(request_host(&request("/", None)), None);
+  }
+
+  #[test]
+  fn ipv6_host_is_taken_without_brackets() {
+    assert_eq!(request_host(&request("/", Some("[::1]:8443"))), Some("::1"));
+    assert_eq!(request_host(&request("/", Some("[2001:db8::7]"))), Some("2001:db8::7"));
+    assert_eq!(request_host(&request("https://[::1]:8443/", None)), Some("::1"));
+  }
+
+  #[test]
+  fn authority_is_preferred_to_host_header() {
+    let req = request("https://api.example.com:8443/v1", Some("www.example.com"));
+    assert_eq!(request_host(&req), Some("api.example.com"));
+  }
+
+  #[test]
+  fn host_routers_are_mounted_once() {
+    let routers = HostRouters::default();
+    routers.register("api.example.com", Router::new());
+    routers.register("www.example.com", Router::new());
+    let hosts = |routers: Vec<(String, Router)>| routers.into_iter().map(|(host, _)| host).collect::<Vec<_>>();
+    assert_eq!(hosts(routers.take()), ["api.example.com", "www.example.com"]);
+    assert!(routers.take().is_empty());
+
+    routers.clone().register("admin.example.com", Router::new());
+    assert_eq!(hosts(routers.take()), ["admin.example.com"]);
+  }
 }
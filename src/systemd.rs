@@ -0,0 +1,249 @@
+//! systemd integration module.
+//!
+//! Supports socket activation (the listeners passed with `LISTEN_FDS`, see `systemd_socket` startup variant) and
+//! service notifications (`READY=1`, `STOPPING=1` and watchdog pings sent to `NOTIFY_SOCKET`). Both are plain
+//! environment variables and local sockets, so they work with any supervisor implementing the protocol.
+
+use std::collections::HashSet;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use cc_utils::prelude::*;
+
+/// First file descriptor passed by systemd.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Listeners passed by systemd; read from the environment on the first use.
+struct ListenFds {
+  /// File descriptors with their names.
+  fds: Vec<(RawFd, String)>,
+  /// File descriptors which are already taken by the listeners.
+  taken: HashSet<RawFd>,
+}
+
+static LISTEN_FDS: Mutex<Option<ListenFds>> = Mutex::new(None);
+
+/// Set once `STOPPING=1` is sent, so it's sent only once.
+static STOPPING_SENT: AtomicBool = AtomicBool::new(false);
+
+/// Listener passed by systemd.
+pub(crate) enum ListenFd {
+  Tcp(tokio::net::TcpListener),
+  Unix(tokio::net::UnixListener),
+}
+
+fn error(message: String) -> ErrorResponse {
+  ErrorResponse::from(message).with_500_pub().build()
+}
+
+/// Parses `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` values to the file descriptors passed to the process with
+/// ID `pid`, with their names.
+fn parse_listen_fds(
+  pid: u32,
+  listen_pid: Option<&str>,
+  listen_fds: Option<&str>,
+  listen_fdnames: Option<&str>,
+) -> MResult<Vec<(RawFd, String)>> {
+  if let Some(listen_pid) = listen_pid
+    && listen_pid.parse::<u32>().ok() != Some(pid)
+  {
+    return Err(error("`LISTEN_PID` doesn't match the server's process ID.".into()));
+  }
+  let count = listen_fds
+    .and_then(|count| count.parse::<RawFd>().ok())
+    .filter(|count| *count > 0)
+    .ok_or_else(|| {
+      error("No sockets are passed with `LISTEN_FDS`; is the server started by socket activation?".into())
+    })?;
+  let mut names = listen_fdnames.unwrap_or_default().split(':');
+  Ok(
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+      .map(|fd| (fd, names.next().unwrap_or_default().to_string()))
+      .collect(),
+  )
+}
+
+/// Reads the file descriptors passed to this process.
+///
+/// The variables are left as is; `auto_migrate_bin` is started without them, so it doesn't take the descriptors for its
+/// own.
+fn read_listen_fds() -> MResult<Vec<(RawFd, String)>> {
+  parse_listen_fds(
+    std::process::id(),
+    std::env::var("LISTEN_PID").ok().as_deref(),
+    std::env::var("LISTEN_FDS").ok().as_deref(),
+    std::env::var("LISTEN_FDNAMES").ok().as_deref(),
+  )
+}
+
+/// Takes the listener passed by systemd: the one named `name` (`FileDescriptorName=` of the socket unit), or the first
+/// one if the name isn't set.
+pub(crate) fn take_listen_fd(name: Option<&str>) -> MResult<ListenFd> {
+  let mut guard = LISTEN_FDS
+    .lock()
+    .map_err(|_| error("The listeners' lock is poisoned.".into()))?;
+  if guard.is_none() {
+    *guard = Some(ListenFds {
+      fds: read_listen_fds()?,
+      taken: HashSet::new(),
+    });
+  }
+  let Some(state) = guard.as_mut() else {
+    return Err(error("No sockets are passed with `LISTEN_FDS`.".into()));
+  };
+  let fd = match name {
+    Some(name) => state
+      .fds
+      .iter()
+      .find(|(_, fd_name)| fd_name == name)
+      .map(|(fd, _)| *fd)
+      .ok_or_else(|| error(format!("No socket named `{}` is passed with `LISTEN_FDNAMES`.", name)))?,
+    None => state.fds[0].0,
+  };
+  if !state.taken.insert(fd) {
+    return Err(error(format!(
+      "The socket with file descriptor {} is already used by another listener.",
+      fd
+    )));
+  }
+  drop(guard);
+
+  // SAFETY: systemd passes the descriptors starting from 3 to this process, and each one is taken only once.
+  let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+  let tcp = std::net::TcpListener::from(fd);
+  if tcp.local_addr().is_ok() {
+    tcp.set_nonblocking(true)?;
+    return Ok(ListenFd::Tcp(tokio::net::TcpListener::from_std(tcp)?));
+  }
+  let unix = std::os::unix::net::UnixListener::from(OwnedFd::from(tcp));
+  unix.set_nonblocking(true)?;
+  Ok(ListenFd::Unix(tokio::net::UnixListener::from_std(unix)?))
+}
+
+/// Sends the state to the service manager, e.g. `READY=1`.
+///
+/// Returns `Ok(false)` if the server isn't started by systemd (`NOTIFY_SOCKET` isn't set).
+pub fn notify(state: &str) -> std::io::Result<bool> {
+  let Some(path) = std::env::var_os("NOTIFY_SOCKET").filter(|path| !path.is_empty()) else {
+    return Ok(false);
+  };
+  send_notification(&path.to_string_lossy(), state)?;
+  Ok(true)
+}
+
+/// Sends the state to the notification socket: the path or the abstract socket name starting with `@`.
+fn send_notification(path: &str, state: &str) -> std::io::Result<()> {
+  let socket = UnixDatagram::unbound()?;
+  match path.strip_prefix('@') {
+    #[cfg(target_os = "linux")]
+    Some(name) => {
+      use std::os::linux::net::SocketAddrExt;
+
+      let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+      socket.send_to_addr(state.as_bytes(), &addr)?;
+    }
+    _ => {
+      socket.send_to(state.as_bytes(), path)?;
+    }
+  }
+  Ok(())
+}
+
+/// Returns the watchdog interval requested by the service manager, if any.
+fn watchdog_interval() -> Option<Duration> {
+  if let Ok(pid) = std::env::var("WATCHDOG_PID")
+    && pid.parse::<u32>().ok() != Some(std::process::id())
+  {
+    return None;
+  }
+  let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+  (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Notifies the service manager that the server is ready and starts the watchdog pings at half the requested interval.
+pub(crate) fn notify_ready() -> Option<tokio::task::JoinHandle<()>> {
+  match notify("READY=1") {
+    Ok(true) => tracing::debug!("Notified the service manager that the server is ready"),
+    Ok(false) => return None,
+    Err(e) => tracing::warn!("Failed to notify the service manager: {}", e),
+  }
+
+  let interval = watchdog_interval()? / 2;
+  Some(tokio::spawn(async move {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+      ticker.tick().await;
+      if let Err(e) = notify("WATCHDOG=1") {
+        tracing::warn!("Failed to send the watchdog ping: {}", e);
+      }
+    }
+  }))
+}
+
+/// Notifies the service manager that the server is stopping; the following calls do nothing.
+pub(crate) fn notify_stopping() {
+  if STOPPING_SENT.swap(true, Ordering::SeqCst) {
+    return;
+  }
+  if let Err(e) = notify("STOPPING=1") {
+    tracing::warn!("Failed to notify the service manager: {}", e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_listen_fds() {
+    let fds = parse_listen_fds(42, Some("42"), Some("2"), Some("http:admin")).unwrap();
+    assert_eq!(fds, vec![(3, "http".to_string()), (4, "admin".to_string())]);
+
+    let fds = parse_listen_fds(42, None, Some("2"), Some("http")).unwrap();
+    assert_eq!(fds, vec![(3, "http".to_string()), (4, String::new())]);
+  }
+
+  #[test]
+  fn rejects_foreign_or_missing_listen_fds() {
+    assert!(parse_listen_fds(42, Some("43"), Some("1"), None).is_err());
+    assert!(parse_listen_fds(42, Some("pid"), Some("1"), None).is_err());
+    assert!(parse_listen_fds(42, Some("42"), None, None).is_err());
+    assert!(parse_listen_fds(42, Some("42"), Some("0"), None).is_err());
+    assert!(parse_listen_fds(42, Some("42"), Some("-1"), None).is_err());
+  }
+
+  fn receive(socket: &UnixDatagram) -> String {
+    let mut buffer = [0; 64];
+    let size = socket.recv(&mut buffer).unwrap();
+    String::from_utf8_lossy(&buffer[..size]).into_owned()
+  }
+
+  #[test]
+  fn sends_notification_to_socket_path() {
+    let path = std::env::temp_dir().join(format!("cc-server-kit-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    send_notification(&path.to_string_lossy(), "READY=1").unwrap();
+    send_notification(&path.to_string_lossy(), "WATCHDOG=1").unwrap();
+    assert_eq!(receive(&socket), "READY=1");
+    assert_eq!(receive(&socket), "WATCHDOG=1");
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn sends_notification_to_abstract_socket() {
+    use std::os::linux::net::SocketAddrExt;
+
+    let name = format!("cc-server-kit-notify-{}", std::process::id());
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let socket = UnixDatagram::bind_addr(&addr).unwrap();
+
+    send_notification(&format!("@{}", name), "STOPPING=1").unwrap();
+    assert_eq!(receive(&socket), "STOPPING=1");
+  }
+}
@@ -0,0 +1,269 @@
+//! OpenTelemetry export module.
+//!
+//! Builds the OTLP exporters and the traces' and logs' providers from `open_telemetry_endpoint` and `open_telemetry`
+//! settings: gRPC or HTTP/protobuf transport with the headers, batch or immediate export, and parent-based sampling.
+//! `https://` endpoints are reached over TLS verified with the system's root certificates.
+
+use opentelemetry_otlp::{LogExporter, SpanExporter, WithExportConfig, WithHttpConfig, WithTonicConfig};
+use opentelemetry_sdk::logs::{self, BatchLogProcessor, LoggerProvider};
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::{self, BatchSpanProcessor, RandomIdGenerator, Sampler, TracerProvider};
+use salvo::http::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::Duration;
+use tonic::metadata::MetadataMap;
+use tonic::transport::ClientTlsConfig;
+
+use cc_utils::prelude::*;
+
+use crate::generic_setup::{
+  DEFAULT_FLUSH_INTERVAL, DEFAULT_MAX_QUEUE_SIZE, DEFAULT_SAMPLING_RATIO, OpenTelemetrySettings, OtlpProtocol,
+  otel_resource,
+};
+
+fn error(message: String) -> ErrorResponse {
+  ErrorResponse::from(message).with_500_pub().build()
+}
+
+/// Converts the headers to gRPC metadata.
+fn metadata(settings: &OpenTelemetrySettings) -> MResult<MetadataMap> {
+  let mut headers = HeaderMap::new();
+  for (name, value) in &settings.headers {
+    headers.insert(
+      HeaderName::from_bytes(name.as_bytes()).map_err(|e| error(format!("Incorrect OTLP header `{}`: {}", name, e)))?,
+      HeaderValue::from_str(value).map_err(|e| error(format!("Incorrect value of OTLP header `{}`: {}", name, e)))?,
+    );
+  }
+  Ok(MetadataMap::from_headers(headers))
+}
+
+/// Sets the gRPC endpoint and headers; `https://` endpoints get TLS with the system's root certificates.
+fn with_tonic_config<B: WithExportConfig + WithTonicConfig>(
+  builder: B,
+  endpoint: &str,
+  settings: &OpenTelemetrySettings,
+) -> MResult<B> {
+  let builder = builder.with_endpoint(endpoint).with_metadata(metadata(settings)?);
+  Ok(if endpoint.starts_with("https://") {
+    builder.with_tls_config(ClientTlsConfig::new().with_native_roots())
+  } else {
+    builder
+  })
+}
+
+fn http_headers(settings: &OpenTelemetrySettings) -> HashMap<String, String> {
+  settings.headers.clone().into_iter().collect()
+}
+
+/// Returns HTTP endpoint of the signal, e.g. `http://localhost:4318/v1/traces`.
+fn signal_url(endpoint: &str, signal: &str) -> String {
+  format!("{}/v1/{}", endpoint.trim_end_matches('/'), signal)
+}
+
+fn span_exporter(endpoint: &str, settings: &OpenTelemetrySettings) -> MResult<SpanExporter> {
+  match settings.protocol {
+    OtlpProtocol::Grpc => with_tonic_config(SpanExporter::builder().with_tonic(), endpoint, settings)?.build(),
+    OtlpProtocol::HttpProtobuf => SpanExporter::builder()
+      .with_http()
+      .with_endpoint(signal_url(endpoint, "traces"))
+      .with_headers(http_headers(settings))
+      .build(),
+  }
+  .map_err(|e| error(format!("Failed to initialize OTLP traces exporter: {}", e)))
+}
+
+fn log_exporter(endpoint: &str, settings: &OpenTelemetrySettings) -> MResult<LogExporter> {
+  match settings.protocol {
+    OtlpProtocol::Grpc => with_tonic_config(LogExporter::builder().with_tonic(), endpoint, settings)?.build(),
+    OtlpProtocol::HttpProtobuf => LogExporter::builder()
+      .with_http()
+      .with_endpoint(signal_url(endpoint, "logs"))
+      .with_headers(http_headers(settings))
+      .build(),
+  }
+  .map_err(|e| error(format!("Failed to initialize OTLP logs exporter: {}", e)))
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn metric_exporter(
+  endpoint: &str,
+  settings: &OpenTelemetrySettings,
+) -> MResult<opentelemetry_otlp::MetricExporter> {
+  use opentelemetry_otlp::MetricExporter;
+
+  match settings.protocol {
+    OtlpProtocol::Grpc => with_tonic_config(MetricExporter::builder().with_tonic(), endpoint, settings)?.build(),
+    OtlpProtocol::HttpProtobuf => MetricExporter::builder()
+      .with_http()
+      .with_endpoint(signal_url(endpoint, "metrics"))
+      .with_headers(http_headers(settings))
+      .build(),
+  }
+  .map_err(|e| error(format!("Failed to initialize OTLP metrics exporter: {}", e)))
+}
+
+fn is_batch(settings: &OpenTelemetrySettings) -> bool {
+  settings.batch.is_none_or(|v| v)
+}
+
+/// Builds the traces' provider; the root spans are sampled by `sampling_ratio`, the others follow their parent.
+pub(crate) fn tracer_provider(
+  app_name: &str,
+  endpoint: &str,
+  settings: &OpenTelemetrySettings,
+) -> MResult<TracerProvider> {
+  let ratio = settings.sampling_ratio.unwrap_or(DEFAULT_SAMPLING_RATIO);
+  if !(0.0..=1.0).contains(&ratio) {
+    return Err(error(format!("The sampling ratio must be from 0 to 1, not {}.", ratio)));
+  }
+
+  let exporter = span_exporter(endpoint, settings)?;
+  let provider = TracerProvider::builder()
+    .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))))
+    .with_id_generator(RandomIdGenerator::default())
+    .with_max_events_per_span(32)
+    .with_max_attributes_per_span(64)
+    .with_resource(otel_resource(app_name, &settings.resource_attributes));
+  let provider = if is_batch(settings) {
+    let config = trace::BatchConfigBuilder::default()
+      .with_max_queue_size(settings.max_queue_size.unwrap_or(DEFAULT_MAX_QUEUE_SIZE))
+      .with_scheduled_delay(Duration::from_millis(
+        settings.flush_interval.unwrap_or(DEFAULT_FLUSH_INTERVAL),
+      ));
+    provider.with_span_processor(
+      BatchSpanProcessor::builder(exporter, Tokio)
+        .with_batch_config(config.build())
+        .build(),
+    )
+  } else {
+    provider.with_simple_exporter(exporter)
+  };
+  Ok(provider.build())
+}
+
+/// Builds the logs' provider.
+pub(crate) fn logger_provider(
+  app_name: &str,
+  endpoint: &str,
+  settings: &OpenTelemetrySettings,
+) -> MResult<LoggerProvider> {
+  let exporter = log_exporter(endpoint, settings)?;
+  let provider = LoggerProvider::builder().with_resource(otel_resource(app_name, &settings.resource_attributes));
+  let provider = if is_batch(settings) {
+    let config = logs::BatchConfigBuilder::default()
+      .with_max_queue_size(settings.max_queue_size.unwrap_or(DEFAULT_MAX_QUEUE_SIZE))
+      .with_scheduled_delay(Duration::from_millis(
+        settings.flush_interval.unwrap_or(DEFAULT_FLUSH_INTERVAL),
+      ));
+    provider.with_log_processor(
+      BatchLogProcessor::builder(exporter, Tokio)
+        .with_batch_config(config.build())
+        .build(),
+    )
+  } else {
+    provider.with_simple_exporter(exporter)
+  };
+  Ok(provider.build())
+}
+
+/// Exports the pending data and stops the exporter; registered as a shutdown hook, since the batches would be lost
+/// otherwise.
+pub(crate) async fn shutdown_exporter<E: Display + Send + 'static>(
+  signal: &'static str,
+  shutdown: impl FnOnce() -> Result<(), E> + Send + 'static,
+) {
+  match tokio::task::spawn_blocking(shutdown).await {
+    Ok(Ok(())) => {}
+    Ok(Err(e)) => tracing::warn!("Failed to shut OTLP {} exporter down: {}", signal, e),
+    Err(e) => tracing::warn!("Failed to shut OTLP {} exporter down: {}", signal, e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use opentelemetry::logs::{LogRecord, Logger, LoggerProvider as _};
+  use salvo::conn::tcp::TcpAcceptor;
+  use salvo::prelude::*;
+  use std::sync::{Arc, Mutex};
+
+  /// OTLP/HTTP collector stand-in remembering the paths the exports are posted to.
+  struct Collector(Arc<Mutex<Vec<String>>>);
+
+  #[handler]
+  impl Collector {
+    async fn handle(&self, req: &mut Request, res: &mut Response) {
+      self.0.lock().unwrap().push(req.uri().path().to_string());
+      res.status_code(StatusCode::OK);
+    }
+  }
+
+  async fn start_collector() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let acceptor = TcpAcceptor::try_from(tokio::net::TcpListener::from_std(listener).unwrap()).unwrap();
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let router = Router::with_path("v1/{signal}").post(Collector(received.clone()));
+    tokio::spawn(Server::new(acceptor).serve(router));
+    (format!("http://{}", addr), received)
+  }
+
+  #[cfg(feature = "metrics")]
+  #[tokio::test(flavor = "multi_thread")]
+  async fn exports_metrics_over_http() {
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+
+    let (endpoint, received) = start_collector().await;
+    let settings = OpenTelemetrySettings {
+      protocol: OtlpProtocol::HttpProtobuf,
+      ..Default::default()
+    };
+
+    let meter_provider = SdkMeterProvider::builder()
+      .with_resource(otel_resource("otlp-test", &settings.resource_attributes))
+      .with_reader(PeriodicReader::builder(metric_exporter(&endpoint, &settings).unwrap(), Tokio).build())
+      .build();
+    meter_provider
+      .meter("otlp-test")
+      .u64_counter("test.requests")
+      .build()
+      .add(1, &[]);
+
+    tokio::task::spawn_blocking(move || meter_provider.force_flush().unwrap())
+      .await
+      .unwrap();
+
+    let paths = received.lock().unwrap().clone();
+    assert!(paths.contains(&"/v1/metrics".to_string()), "{:?}", paths);
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn exports_logs_over_http() {
+    let (endpoint, received) = start_collector().await;
+    let settings = OpenTelemetrySettings {
+      protocol: OtlpProtocol::HttpProtobuf,
+      ..Default::default()
+    };
+
+    let logger_provider = logger_provider("otlp-test", &endpoint, &settings).unwrap();
+    let logger = logger_provider.logger("otlp-test");
+    let mut record = logger.create_log_record();
+    record.set_body("exported".into());
+    logger.emit(record);
+
+    tokio::task::spawn_blocking(move || {
+      for result in logger_provider.force_flush() {
+        result.unwrap();
+      }
+    })
+    .await
+    .unwrap();
+
+    let paths = received.lock().unwrap().clone();
+    assert!(paths.contains(&"/v1/logs".to_string()), "{:?}", paths);
+  }
+}
@@ -0,0 +1,232 @@
+//! Unix domain socket module.
+//!
+//! Prepares the socket file for `unix_socket` startup variant: removes the stale socket left by the previous run,
+//! binds the socket with the file mode and owner applied before it becomes reachable and removes the socket after
+//! graceful shutdown.
+
+use salvo::conn::Listener;
+use salvo::conn::unix::{UnixAcceptor, UnixListener};
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use cc_utils::prelude::*;
+
+/// Parses the socket file mode written in octal, e.g. `660`, `0660` or `0o660`.
+pub fn parse_socket_mode(mode: &str) -> Option<u32> {
+  let digits = mode.strip_prefix("0o").unwrap_or(mode);
+  u32::from_str_radix(digits, 8).ok().filter(|mode| *mode <= 0o7777)
+}
+
+/// Looks up the ID by the name in `/etc/passwd` or `/etc/group`; numeric IDs are returned as is.
+fn lookup_id(database: &str, name: &str) -> Option<u32> {
+  if let Ok(id) = name.parse() {
+    return Some(id);
+  }
+  std::fs::read_to_string(database).ok()?.lines().find_map(|line| {
+    let mut fields = line.split(':');
+    if fields.next()? != name {
+      return None;
+    }
+    fields.nth(1)?.parse().ok()
+  })
+}
+
+/// Resolves the socket owner, `user`, `user:group` or `:group`, to the user and group IDs.
+pub fn resolve_socket_owner(owner: &str) -> Result<(Option<u32>, Option<u32>), String> {
+  let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+  let uid = match user {
+    "" => None,
+    user => Some(lookup_id("/etc/passwd", user).ok_or_else(|| format!("Unknown user `{}`.", user))?),
+  };
+  let gid = match group {
+    "" => None,
+    group => Some(lookup_id("/etc/group", group).ok_or_else(|| format!("Unknown group `{}`.", group))?),
+  };
+  Ok((uid, gid))
+}
+
+/// Removes the socket file left by the previous run.
+///
+/// Fails if the path exists and is not a socket, or if another server still accepts connections on it.
+pub(crate) fn remove_stale_socket(path: &Path) -> MResult<()> {
+  let metadata = match std::fs::symlink_metadata(path) {
+    Ok(metadata) => metadata,
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+    Err(e) => return Err(e.into()),
+  };
+  if !metadata.file_type().is_socket() {
+    return Err(
+      ErrorResponse::from(format!("`{}` exists and is not a socket.", path.display()))
+        .with_500_pub()
+        .build(),
+    );
+  }
+  if UnixStream::connect(path).is_ok() {
+    return Err(
+      ErrorResponse::from(format!("Another server is listening on `{}`.", path.display()))
+        .with_500_pub()
+        .build(),
+    );
+  }
+  tracing::info!("Removing stale socket `{}`", path.display());
+  std::fs::remove_file(path)?;
+  Ok(())
+}
+
+/// Sets the socket file mode and owner.
+pub(crate) fn set_socket_permissions(path: &Path, mode: Option<&str>, owner: Option<&str>) -> MResult<()> {
+  if let Some(mode) = mode {
+    let mode = parse_socket_mode(mode).ok_or_else(|| {
+      ErrorResponse::from(format!(
+        "Incorrect socket mode `{}`; write it in octal, e.g. `660`.",
+        mode
+      ))
+      .with_500_pub()
+      .build()
+    })?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+  }
+  if let Some(owner) = owner {
+    let (uid, gid) = resolve_socket_owner(owner).map_err(|e| ErrorResponse::from(e).with_500_pub().build())?;
+    std::os::unix::fs::chown(path, uid, gid)?;
+  }
+  Ok(())
+}
+
+fn bind_error(e: salvo::Error) -> ErrorResponse {
+  ErrorResponse::from(e.to_string()).with_500_pub().build()
+}
+
+/// Creates the directory only the server's user can enter, replacing the one left by the previous run.
+fn create_private_dir(dir: &Path) -> MResult<()> {
+  if let Err(e) = std::fs::remove_dir_all(dir)
+    && e.kind() != ErrorKind::NotFound
+  {
+    return Err(e.into());
+  }
+  std::fs::DirBuilder::new().mode(0o700).create(dir)?;
+  Ok(())
+}
+
+/// Removes the private directory after the socket in it isn't needed anymore.
+pub(crate) fn remove_private_dir(dir: &Path) {
+  if let Err(e) = std::fs::remove_dir_all(dir)
+    && e.kind() != ErrorKind::NotFound
+  {
+    tracing::warn!("Failed to remove the directory `{}`: {}", dir.display(), e);
+  }
+}
+
+/// Binds the socket at `path` with the file mode and owner set.
+///
+/// The socket is bound in a private directory next to `path` and renamed into place once its permissions are set, so
+/// nobody can connect to it in between.
+pub(crate) async fn bind_socket(path: &Path, mode: Option<&str>, owner: Option<&str>) -> MResult<UnixAcceptor> {
+  let parent = path
+    .parent()
+    .filter(|parent| !parent.as_os_str().is_empty())
+    .unwrap_or(Path::new("."));
+  let private_dir = parent.join(format!(".socket-{}.tmp", std::process::id()));
+  create_private_dir(&private_dir)?;
+
+  let private_path = private_dir.join("socket");
+  let result = async {
+    let acceptor = UnixListener::new(private_path.clone())
+      .try_bind()
+      .await
+      .map_err(bind_error)?;
+    set_socket_permissions(&private_path, mode, owner)?;
+    std::fs::rename(&private_path, path)?;
+    Ok(acceptor)
+  }
+  .await;
+  remove_private_dir(&private_dir);
+  result
+}
+
+/// Serves the listener passed by the service manager, e.g. systemd.
+///
+/// salvo's acceptor binds its socket itself, so it's bound in a private directory and the connections accepted on
+/// `listener` are forwarded to it. Returns the acceptor and the directory to remove once the server has stopped; the
+/// forwarding stops with it.
+pub(crate) async fn forward_listener(listener: tokio::net::UnixListener) -> MResult<(UnixAcceptor, PathBuf)> {
+  let private_dir = std::env::temp_dir().join(format!(
+    "cc-server-kit-{}-fd{}",
+    std::process::id(),
+    listener.as_raw_fd()
+  ));
+  create_private_dir(&private_dir)?;
+  let private_path = private_dir.join("socket");
+  let acceptor = UnixListener::new(private_path.clone())
+    .try_bind()
+    .await
+    .map_err(bind_error)?;
+
+  tokio::spawn(async move {
+    loop {
+      let mut conn = match listener.accept().await {
+        Ok((conn, _)) => conn,
+        Err(e) => {
+          tracing::warn!("Failed to accept the connection: {}", e);
+          continue;
+        }
+      };
+      let mut server = match tokio::net::UnixStream::connect(&private_path).await {
+        Ok(server) => server,
+        Err(e) if e.kind() == ErrorKind::NotFound => break,
+        Err(e) => {
+          tracing::warn!("Failed to forward the connection: {}", e);
+          continue;
+        }
+      };
+      tokio::spawn(async move {
+        let _ = tokio::io::copy_bidirectional(&mut conn, &mut server).await;
+      });
+    }
+  });
+
+  Ok((acceptor, private_dir))
+}
+
+/// Removes the socket file after the server has stopped.
+pub(crate) fn remove_socket(path: &Path) {
+  if let Err(e) = std::fs::remove_file(path)
+    && e.kind() != ErrorKind::NotFound
+  {
+    tracing::warn!("Failed to remove the socket `{}`: {}", path.display(), e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn binds_socket_with_permissions() {
+    let dir = std::env::temp_dir().join(format!("cc-server-kit-socket-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.sock");
+
+    let _acceptor = bind_socket(&path, Some("600"), None).await.unwrap();
+    let metadata = std::fs::symlink_metadata(&path).unwrap();
+    assert!(metadata.file_type().is_socket());
+    assert_eq!(metadata.permissions().mode() & 0o7777, 0o600);
+    assert!(UnixStream::connect(&path).is_ok());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    assert!(remove_stale_socket(&path).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn parses_socket_mode() {
+    assert_eq!(parse_socket_mode("660"), Some(0o660));
+    assert_eq!(parse_socket_mode("0o660"), Some(0o660));
+    assert_eq!(parse_socket_mode("0660"), Some(0o660));
+    assert_eq!(parse_socket_mode("888"), None);
+    assert_eq!(parse_socket_mode("17777"), None);
+  }
+}
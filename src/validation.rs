@@ -0,0 +1,668 @@
+//! Configuration validation module.
+//!
+//! Instead of stopping at the first problem, the whole configuration is checked and all the problems found are
+//! collected into `ConfigReport`, which can be printed in CI or from `--check-config` mode.
+
+use serde::de::DeserializeOwned;
+use serde_yaml::Value;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use cc_utils::prelude::*;
+
+use crate::config::{ConfigSource, load_document, locate_error};
+use crate::generic_setup::{GenericSetup, StartupVariant, TlsSettings};
+use crate::tls::cipher_suite_names;
+#[cfg(unix)]
+use crate::unix_socket::parse_socket_mode;
+
+/// Single configuration problem.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigIssue {
+  /// Configuration file the problem was found in, if known.
+  pub file: Option<PathBuf>,
+  /// Path to the problematic value, e.g. `ssl_key_path` or `database.url`.
+  pub path: Option<String>,
+  /// Line in the file (starting from 1), if known.
+  pub line: Option<usize>,
+  /// Column in the file (starting from 1), if known.
+  pub column: Option<usize>,
+  /// Human-readable description.
+  pub message: String,
+}
+
+impl ConfigIssue {
+  /// Creates the issue with the message only.
+  pub fn new(message: impl Into<String>) -> Self {
+    Self {
+      file: None,
+      path: None,
+      line: None,
+      column: None,
+      message: message.into(),
+    }
+  }
+
+  /// Sets the file the problem was found in.
+  pub fn in_file(mut self, file: &Path) -> Self {
+    self.file = Some(file.to_path_buf());
+    self
+  }
+
+  /// Sets the path to the problematic value.
+  pub fn at(mut self, path: impl Into<String>) -> Self {
+    self.path = Some(path.into());
+    self
+  }
+
+  /// Sets the line and column in the file.
+  pub fn with_location(mut self, line: usize, column: usize) -> Self {
+    self.line = Some(line);
+    self.column = Some(column);
+    self
+  }
+}
+
+impl fmt::Display for ConfigIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(file) = &self.file {
+      write!(f, "{}", file.display())?;
+      if let Some(line) = self.line {
+        write!(f, ":{}", line)?;
+        if let Some(column) = self.column {
+          write!(f, ":{}", column)?;
+        }
+      }
+      write!(f, ": ")?;
+    }
+    if let Some(path) = &self.path {
+      write!(f, "`{}`: ", path)?;
+    }
+    write!(f, "{}", self.message)
+  }
+}
+
+/// All problems found in the configuration.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigReport {
+  /// Problems in the order they were found.
+  pub issues: Vec<ConfigIssue>,
+}
+
+impl ConfigReport {
+  /// Adds the problem to the report.
+  pub fn push(&mut self, issue: ConfigIssue) {
+    self.issues.push(issue);
+  }
+
+  /// Returns `true` if there are no problems.
+  pub fn is_ok(&self) -> bool {
+    self.issues.is_empty()
+  }
+
+  /// Returns `Ok(())` if there are no problems, or the error listing all of them.
+  pub fn into_result(self) -> MResult<()> {
+    if self.is_ok() { Ok(()) } else { Err(self.into()) }
+  }
+
+  /// Checks whether the problem with the value at the path is already reported.
+  ///
+  /// Serde stops at the first problem, and often it is the same one the kit's checks have already described better;
+  /// at the document's root, only the same message is dropped.
+  fn mentions_reported(&self, path: Option<&str>, message: &str) -> bool {
+    self
+      .issues
+      .iter()
+      .any(|issue| issue.path.as_deref() == path && (path.is_some() || issue.message == message))
+  }
+}
+
+impl fmt::Display for ConfigReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_ok() {
+      return write!(f, "The server configuration is OK.");
+    }
+    write!(f, "Found {} problem(s) in the server configuration:", self.issues.len())?;
+    for issue in &self.issues {
+      write!(f, "\n  - {}", issue)?;
+    }
+    Ok(())
+  }
+}
+
+impl From<ConfigReport> for ErrorResponse {
+  fn from(report: ConfigReport) -> Self {
+    ErrorResponse::from(report.to_string()).with_500_pub().build()
+  }
+}
+
+/// Checks the configuration without starting anything and returns all the problems found.
+///
+/// Use it in CI or in `--check-config` mode of your server:
+///
+/// ```rust,ignore
+/// let report = check_generic_config::<Setup>("server-example", None);
+/// println!("{}", report);
+/// std::process::exit(if report.is_ok() { 0 } else { 1 });
+/// ```
+///
+/// To check the file from a custom location, use `ConfigSource::check`.
+pub fn check_generic_config<T: DeserializeOwned + GenericSetup>(app_name: &str, profile: Option<&str>) -> ConfigReport {
+  ConfigSource::with_profile(app_name, profile).check::<T>()
+}
+
+/// Loads, checks and deserializes the configuration; returns the configuration if it could be deserialized.
+pub(crate) fn parse_generic_config<T: DeserializeOwned + GenericSetup>(
+  source: &ConfigSource,
+) -> (Option<T>, ConfigReport) {
+  let mut report = ConfigReport::default();
+  let (document, config_origin) = load_document(source, &mut report);
+  if document.is_null() && !report.is_ok() {
+    return (None, report);
+  }
+
+  check_document(&document, &mut report);
+  let secrets = config_origin.secrets.clone();
+  let files = config_origin.files.clone();
+
+  let config = match serde_path_to_error::deserialize::<_, T>(document) {
+    Ok(mut config) => {
+      let data = config.generic_values_mut();
+      data.app_name = source.app_name().to_string();
+      data.config_origin = config_origin;
+      Some(config)
+    }
+    Err(e) => {
+      let message = e.inner().to_string();
+      let path = e.path().to_string();
+      let path = Some(path.trim_start_matches('.')).filter(|path| !path.is_empty());
+      if !report.mentions_reported(path, &message) {
+        let mut issue = ConfigIssue::new(message.clone());
+        if let Some(path) = path {
+          issue = issue.at(path);
+          if let Some((file, line, column)) = locate_error::<T>(&files, path, &message) {
+            issue = issue.in_file(&file).with_location(line, column);
+          }
+        }
+        report.push(issue);
+      }
+      None
+    }
+  };
+
+  for issue in &mut report.issues {
+    issue.message = secrets.redact(&issue.message);
+  }
+  (config, report)
+}
+
+fn is_set(document: &Value, field: &str) -> bool {
+  document.get(field).is_some_and(|value| !value.is_null())
+}
+
+fn check_document(document: &Value, report: &mut ConfigReport) {
+  let variants = check_startup_variants(document, report);
+  check_tls(document, &variants, report);
+  #[cfg(feature = "force-https")]
+  check_redirect(document, &variants, report);
+  check_security_headers(document, report);
+  check_log_level(document, "log_level", report);
+  check_log_level(document, "log_file_level", report);
+  check_log_rolling(document, report);
+  #[cfg(feature = "otel")]
+  check_open_telemetry(document, report);
+  #[cfg(feature = "oapi")]
+  check_oapi(document, report);
+}
+
+/// Startup variant parsed from the document, with the prefix of its fields' paths, e.g. `listeners[0].`.
+struct ParsedVariant {
+  prefix: String,
+  variant: StartupVariant,
+}
+
+/// Parses and checks the startup variants: the main one, `listeners` and `admin`. Returns the parsed ones.
+fn check_startup_variants(document: &Value, report: &mut ConfigReport) -> Vec<ParsedVariant> {
+  let listeners = document.get("listeners").and_then(Value::as_sequence);
+  let admin = document.get("admin").filter(|admin| !admin.is_null());
+  std::iter::once((document, String::new()))
+    .chain(
+      listeners
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, listener)| (listener, format!("listeners[{}].", index))),
+    )
+    .chain(admin.map(|admin| (admin, "admin.".to_string())))
+    .filter_map(
+      |(value, prefix)| match serde_yaml::from_value::<StartupVariant>(value.clone()) {
+        Ok(variant) => {
+          check_startup(value, &variant, &prefix, report);
+          Some(ParsedVariant { prefix, variant })
+        }
+        Err(e) => {
+          let issue = ConfigIssue::new(e.to_string());
+          report.push(match prefix.strip_suffix('.') {
+            Some(path) => issue.at(path),
+            None => issue,
+          });
+          None
+        }
+      },
+    )
+    .collect()
+}
+
+/// Checks the startup variant; `prefix` is prepended to the paths, e.g. `listeners[0].`.
+fn check_startup(document: &Value, variant: &StartupVariant, prefix: &str, report: &mut ConfigReport) {
+  let at = |field: &str| format!("{}{}", prefix, field);
+
+  if matches!(variant, StartupVariant::HttpLocalhost { .. }) && is_set(document, "server_host") {
+    report.push(ConfigIssue::new("Server will only listen `127.0.0.1` address because of `http_localhost` startup variant. Consider to move to `https_only` or `quinn`.").at(at("server_host")));
+  }
+
+  #[cfg(unix)]
+  if let StartupVariant::UnixSocket {
+    socket_mode: Some(mode),
+    ..
+  } = variant
+    && parse_socket_mode(mode).is_none()
+  {
+    report.push(ConfigIssue::new("Write the socket mode in octal, e.g. `\"660\"`.").at(at("socket_mode")));
+  }
+
+  for (field, file) in variant.ssl_files() {
+    check_readable(file, &at(field), report);
+  }
+  for (name, host) in variant.ssl_hosts().into_iter().flatten() {
+    let at = |field: &str| format!("{}ssl_hosts.{}.{}", prefix, name, field);
+    check_readable(&host.ssl_crt_path, &at("ssl_crt_path"), report);
+    check_readable(&host.ssl_key_path, &at("ssl_key_path"), report);
+  }
+  if variant.is_quic() && variant.verifies_clients() {
+    report.push(
+      ConfigIssue::new("The client certificates can't be verified over QUIC; use `https_only` for mutual TLS.")
+        .at(at("ssl_client_ca_path")),
+    );
+  }
+}
+
+/// Checks that the file can be read; `path` is the path to the field naming it.
+fn check_readable(file: &str, path: &str, report: &mut ConfigReport) {
+  if let Err(e) = std::fs::read(file) {
+    report.push(ConfigIssue::new(format!("Failed to read the file `{}`: {}", file, e)).at(path));
+  }
+}
+
+/// Checks the TLS parameters and that they aren't set for the ACME listeners, which can't apply them.
+fn check_tls(document: &Value, variants: &[ParsedVariant], report: &mut ConfigReport) {
+  let Some(settings) = document.get("tls").filter(|settings| !settings.is_null()) else {
+    return;
+  };
+  if let Some(Value::Sequence(suites)) = settings.get("cipher_suites") {
+    let available = cipher_suite_names();
+    for (index, suite) in suites.iter().enumerate() {
+      if !suite
+        .as_str()
+        .is_some_and(|suite| available.iter().any(|name| name == suite))
+      {
+        report.push(
+          ConfigIssue::new(format!(
+            "Unknown cipher suite; available ones are: {}.",
+            available.join(", ")
+          ))
+          .at(format!("tls.cipher_suites[{}]", index)),
+        );
+      }
+    }
+  }
+
+  let settings = match serde_path_to_error::deserialize::<_, TlsSettings>(settings.clone()) {
+    Ok(settings) => settings,
+    Err(e) => {
+      let path = format!("tls.{}", e.path());
+      if !report.mentions_reported(Some(&path), "") {
+        report.push(ConfigIssue::new(e.inner().to_string()).at(path));
+      }
+      return;
+    }
+  };
+  if settings == TlsSettings::default() {
+    return;
+  }
+  for parsed in variants.iter().filter(|parsed| parsed.variant.is_acme()) {
+    report.push(
+      ConfigIssue::new("`tls` settings can't be applied to the ACME listeners; remove them or use certificate files.")
+        .at(format!("{}startup_type", parsed.prefix)),
+    );
+  }
+}
+
+#[cfg(feature = "force-https")]
+fn check_redirect(document: &Value, variants: &[ParsedVariant], report: &mut ConfigReport) {
+  let Some(port) = document.get("redirect_http_port").filter(|port| !port.is_null()) else {
+    return;
+  };
+  if variants.first().is_none_or(|parsed| !parsed.prefix.is_empty()) {
+    return;
+  }
+  let https = variants
+    .iter()
+    .find(|parsed| parsed.prefix != "admin." && parsed.variant.is_https());
+  match https {
+    None => report.push(
+      ConfigIssue::new("HTTP can be redirected only to a listener serving HTTPS over TCP, which `quinn_only` isn't.")
+        .at("redirect_http_port"),
+    ),
+    Some(https) if port.as_u64().is_some() && port.as_u64() == https.variant.server_port().map(u64::from) => {
+      report.push(ConfigIssue::new("The redirect port must differ from `server_port`.").at("redirect_http_port"))
+    }
+    Some(_) => {}
+  }
+}
+
+#[cfg(feature = "otel")]
+fn check_open_telemetry(document: &Value, report: &mut ConfigReport) {
+  if let Some(ratio) = document
+    .get("open_telemetry")
+    .and_then(|settings| settings.get("sampling_ratio"))
+    .and_then(Value::as_f64)
+    && !(0.0..=1.0).contains(&ratio)
+  {
+    report.push(ConfigIssue::new("The sampling ratio must be from 0 to 1.").at("open_telemetry.sampling_ratio"));
+  }
+  if let Some(endpoint) = document
+    .get("open_telemetry_endpoint")
+    .filter(|endpoint| !endpoint.is_null())
+  {
+    if !endpoint
+      .as_str()
+      .is_some_and(|endpoint| endpoint.starts_with("http://") || endpoint.starts_with("https://"))
+    {
+      report.push(
+        ConfigIssue::new("The endpoint must start with `http://` or `https://`, e.g. `http://localhost:4317`.")
+          .at("open_telemetry_endpoint"),
+      );
+    }
+    return;
+  }
+  for field in ["open_telemetry_metrics", "open_telemetry_logs"] {
+    if document.get(field).and_then(Value::as_bool) == Some(true) {
+      report.push(ConfigIssue::new("The export needs `open_telemetry_endpoint` to be set.").at(field));
+    }
+  }
+}
+
+fn check_security_headers(document: &Value, report: &mut ConfigReport) {
+  let Some(settings) = document.get("security_headers").filter(|settings| !settings.is_null()) else {
+    return;
+  };
+  for field in [
+    "content_security_policy",
+    "frame_options",
+    "referrer_policy",
+    "permissions_policy",
+  ] {
+    if let Some(value) = settings.get(field).and_then(Value::as_str)
+      && !value.chars().all(|c| c == '\t' || (' '..='~').contains(&c))
+    {
+      report.push(
+        ConfigIssue::new("The header value may contain only visible ASCII characters and spaces.")
+          .at(format!("security_headers.{}", field)),
+      );
+    }
+  }
+  if let Some(frame_options) = settings.get("frame_options").and_then(Value::as_str)
+    && !["", "DENY", "SAMEORIGIN"].contains(&frame_options.to_ascii_uppercase().as_str())
+  {
+    report.push(ConfigIssue::new("Choose `DENY` or `SAMEORIGIN` frame options.").at("security_headers.frame_options"));
+  }
+}
+
+fn check_log_level(document: &Value, field: &str, report: &mut ConfigReport) {
+  let Some(value) = document.get(field).filter(|value| !value.is_null()) else {
+    return;
+  };
+  if !value
+    .as_str()
+    .is_some_and(|level| ["error", "warn", "info", "debug", "trace"].contains(&level))
+  {
+    report.push(
+      ConfigIssue::new(
+        "Incorrect logging level. Choose one of the options: `error`, `warn`, `info`, `debug`, `trace`.",
+      )
+      .at(field),
+    );
+  }
+}
+
+fn check_log_rolling(document: &Value, report: &mut ConfigReport) {
+  let Some(value) = document.get("log_rolling").filter(|value| !value.is_null()) else {
+    return;
+  };
+  if !value
+    .as_str()
+    .is_some_and(|rolling| ["never", "daily", "hourly", "minutely"].contains(&rolling))
+  {
+    report.push(
+      ConfigIssue::new(
+        "Incorrect level of log rotation. Choose one of the options: `never`, `daily`, `hourly`, `minutely`.",
+      )
+      .at("log_rolling"),
+    );
+  }
+}
+
+#[cfg(feature = "oapi")]
+fn check_oapi(document: &Value, report: &mut ConfigReport) {
+  if document.get("allow_oapi_access").and_then(Value::as_bool) != Some(true) {
+    return;
+  }
+  for (field, message) in [
+    ("oapi_name", "The API name for OAPI is not specified."),
+    ("oapi_ver", "The API version for OAPI is not specified."),
+    ("oapi_api_addr", "The path to OAPI was not specified."),
+  ] {
+    if !is_set(document, field) {
+      report.push(ConfigIssue::new(message).at(field));
+    }
+  }
+  if let Some(frontend) = document.get("oapi_frontend_type").and_then(Value::as_str)
+    && !["Scalar", "SwaggerUI"].contains(&frontend)
+  {
+    report.push(ConfigIssue::new("Choose `Scalar` or `SwaggerUI` OAPI frontend.").at("oapi_frontend_type"));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn missing_field_is_reported_at_its_variant() {
+    let document = serde_yaml::from_str(
+      "
+startup_type: http_localhost
+listeners:
+  - { startup_type: https_only, server_host: 0.0.0.0, ssl_key_path: Cargo.toml }
+admin: { startup_type: unsafe_http }
+",
+    )
+    .unwrap();
+    let mut report = ConfigReport::default();
+    let variants = check_startup_variants(&document, &mut report);
+    assert_eq!(variants.len(), 1);
+    let issues = report
+      .issues
+      .iter()
+      .map(|issue| (issue.path.as_deref().unwrap_or_default(), issue.message.as_str()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      issues,
+      vec![
+        ("listeners[0]", "missing field `ssl_crt_path`"),
+        ("admin", "missing field `server_host`")
+      ]
+    );
+  }
+
+  #[test]
+  fn unknown_startup_type_is_reported_by_serde() {
+    let mut report = ConfigReport::default();
+    check_startup_variants(&serde_yaml::from_str("startup_type: https").unwrap(), &mut report);
+    assert_eq!(report.issues.len(), 1);
+    assert_eq!(report.issues[0].path, None);
+    assert!(report.issues[0].message.starts_with("unknown variant `https`"));
+  }
+
+  #[test]
+  fn only_errors_at_reported_paths_are_dropped() {
+    let mut report = ConfigReport::default();
+    report.push(ConfigIssue::new("missing field `ssl_crt_path`").at("listeners[0]"));
+    report.push(ConfigIssue::new("missing field `server_host`"));
+    assert!(report.mentions_reported(Some("listeners[0]"), "invalid type: integer `1`, expected a string"));
+    assert!(!report.mentions_reported(Some("listeners[1]"), "missing field `ssl_crt_path`"));
+    assert!(report.mentions_reported(None, "missing field `server_host`"));
+    assert!(!report.mentions_reported(None, "missing field `app_url`"));
+  }
+
+  fn tls_issues(yaml: &str) -> Vec<String> {
+    let document = serde_yaml::from_str(yaml).unwrap();
+    let mut report = ConfigReport::default();
+    let variants = check_startup_variants(&document, &mut ConfigReport::default());
+    check_tls(&document, &variants, &mut report);
+    report.issues.into_iter().filter_map(|issue| issue.path).collect()
+  }
+
+  #[test]
+  fn tls_names_are_checked() {
+    let yaml = "
+startup_type: http_localhost
+tls:
+  min_version: tls1.1
+  cipher_suites: [TLS13_AES_256_GCM_SHA384, TLS_RSA_WITH_RC4_128_SHA]
+";
+    assert_eq!(tls_issues(yaml), vec!["tls.cipher_suites[1]", "tls.min_version"]);
+
+    let yaml = "
+startup_type: http_localhost
+tls: { min_version: tls1.3, cipher_suites: [TLS13_AES_128_GCM_SHA256] }
+";
+    assert!(tls_issues(yaml).is_empty());
+  }
+
+  #[test]
+  fn client_certificates_over_quic_are_an_error() {
+    let document = serde_yaml::from_str(
+      "
+startup_type: quinn
+server_host: 0.0.0.0
+ssl_crt_path: Cargo.toml
+ssl_key_path: Cargo.toml
+ssl_client_ca_path: Cargo.toml
+listeners:
+  - { startup_type: https_only, server_host: 0.0.0.0, server_port: 8443, ssl_crt_path: Cargo.toml, ssl_key_path: Cargo.toml, ssl_client_ca_path: Cargo.toml }
+",
+    )
+    .unwrap();
+    let mut report = ConfigReport::default();
+    check_document(&document, &mut report);
+    let paths = report
+      .issues
+      .into_iter()
+      .filter_map(|issue| issue.path)
+      .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["ssl_client_ca_path"]);
+  }
+
+  #[cfg(all(feature = "acme", feature = "http3"))]
+  #[test]
+  fn tls_with_acme_is_an_error() {
+    let yaml = "
+startup_type: https_acme
+server_host: 0.0.0.0
+acme_domain: example.com
+listeners:
+  - { startup_type: http_localhost }
+  - { startup_type: quinn_acme, server_host: 0.0.0.0, acme_domain: example.com }
+tls: { min_version: tls1.3 }
+";
+    assert_eq!(tls_issues(yaml), vec!["startup_type", "listeners[1].startup_type"]);
+
+    let yaml = "
+startup_type: https_acme
+server_host: 0.0.0.0
+acme_domain: example.com
+tls: { min_version: tls1.2, cipher_suites: [], alpn_protocols: [] }
+";
+    assert!(tls_issues(yaml).is_empty());
+  }
+
+  #[cfg(all(feature = "force-https", feature = "http3"))]
+  #[test]
+  fn redirect_needs_https_over_tcp() {
+    let issues = |yaml: &str| {
+      let document = serde_yaml::from_str(yaml).unwrap();
+      let mut report = ConfigReport::default();
+      let variants = check_startup_variants(&document, &mut ConfigReport::default());
+      check_redirect(&document, &variants, &mut report);
+      report.issues.into_iter().map(|issue| issue.message).collect::<Vec<_>>()
+    };
+    let tls = "server_host: 0.0.0.0, ssl_crt_path: Cargo.toml, ssl_key_path: Cargo.toml";
+
+    assert_eq!(
+      issues(&format!(
+        "{{ startup_type: quinn_only, {}, redirect_http_port: 80 }}",
+        tls
+      ))
+      .len(),
+      1
+    );
+    assert!(
+      issues(&format!(
+        "{{ startup_type: https_only, {}, server_port: 443, redirect_http_port: 80 }}",
+        tls
+      ))
+      .is_empty()
+    );
+    assert!(
+      issues(&format!(
+        "{{ startup_type: quinn_only, {0}, redirect_http_port: 80, listeners: [{{ startup_type: https_only, {0}, server_port: 443 }}] }}",
+        tls
+      ))
+      .is_empty()
+    );
+    assert_eq!(
+      issues(&format!(
+        "{{ startup_type: https_only, {}, server_port: 443, redirect_http_port: 443 }}",
+        tls
+      )),
+      vec!["The redirect port must differ from `server_port`."]
+    );
+    assert!(issues("{ startup_type: https_only, redirect_http_port: 80 }").is_empty());
+  }
+
+  #[cfg(feature = "otel")]
+  #[test]
+  fn telemetry_endpoint_needs_a_scheme() {
+    let issues = |yaml: &str| {
+      let mut report = ConfigReport::default();
+      check_open_telemetry(&serde_yaml::from_str(yaml).unwrap(), &mut report);
+      report
+        .issues
+        .into_iter()
+        .filter_map(|issue| issue.path)
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      issues("{ open_telemetry_endpoint: localhost:4317 }"),
+      vec!["open_telemetry_endpoint"]
+    );
+    assert!(issues("{ open_telemetry_endpoint: https://otlp.example.com:4318, open_telemetry_logs: true }").is_empty());
+    assert_eq!(
+      issues("{ open_telemetry_metrics: true }"),
+      vec!["open_telemetry_metrics"]
+    );
+  }
+}